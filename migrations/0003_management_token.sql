@@ -0,0 +1,5 @@
+-- Hash of the secret handed to the creator of a clip
+ALTER TABLE clips ADD COLUMN management_token TEXT;
+
+-- Admin keys may manage any clip
+ALTER TABLE api_keys ADD COLUMN admin BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Clips that are deleted as soon as they are read once
+ALTER TABLE clips ADD COLUMN burn_after_read BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Number of reads after which a clip expires
+ALTER TABLE clips ADD COLUMN max_views BIGINT;
@@ -0,0 +1,11 @@
+-- Prior versions of a clip, numbered from 1 in the order they were replaced
+CREATE TABLE IF NOT EXISTS clip_revisions
+(
+    clip_id     TEXT NOT NULL REFERENCES clips (id) ON DELETE CASCADE,
+    revision    BIGINT NOT NULL,
+    content     TEXT NOT NULL,
+    title       TEXT,
+    expires_at  DATETIME,
+    revised_at  DATETIME NOT NULL,
+    PRIMARY KEY (clip_id, revision)
+);
@@ -0,0 +1,5 @@
+-- API key that created a clip, clips created through the web form have no owner
+ALTER TABLE clips ADD COLUMN owner BLOB REFERENCES api_keys (api_key) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS clips_owner_created_at ON clips (owner, created_at, id);
+CREATE INDEX IF NOT EXISTS clips_owner_views ON clips (owner, views, id);
@@ -0,0 +1,29 @@
+-- Full-text index over clip titles and content, password-protected clips are never indexed
+CREATE VIRTUAL TABLE IF NOT EXISTS clips_search USING fts5
+(
+    clip_id UNINDEXED,
+    title,
+    content
+);
+
+INSERT INTO clips_search (clip_id, title, content)
+SELECT id, title, content FROM clips WHERE password IS NULL;
+
+CREATE TRIGGER IF NOT EXISTS clips_search_insert AFTER INSERT ON clips
+WHEN new.password IS NULL
+BEGIN
+    INSERT INTO clips_search (clip_id, title, content) VALUES (new.id, new.title, new.content);
+END;
+
+-- view counting updates clips constantly, only changes to indexed columns touch the index
+CREATE TRIGGER IF NOT EXISTS clips_search_update AFTER UPDATE OF title, content, password ON clips
+BEGIN
+    DELETE FROM clips_search WHERE clip_id = old.id;
+    INSERT INTO clips_search (clip_id, title, content)
+    SELECT new.id, new.title, new.content WHERE new.password IS NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS clips_search_delete AFTER DELETE ON clips
+BEGIN
+    DELETE FROM clips_search WHERE clip_id = old.id;
+END;
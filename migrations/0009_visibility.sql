@@ -0,0 +1,6 @@
+-- Who may find and read a clip, existing clips keep behaving as unlisted ones
+ALTER TABLE clips ADD COLUMN visibility TEXT NOT NULL DEFAULT 'unlisted'
+    CHECK (visibility IN ('public', 'unlisted', 'private'));
+
+CREATE INDEX IF NOT EXISTS clips_visibility_created_at ON clips (visibility, created_at, id);
+CREATE INDEX IF NOT EXISTS clips_visibility_views ON clips (visibility, views, id);
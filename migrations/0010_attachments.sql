@@ -0,0 +1,10 @@
+-- Clips can carry a file instead of text, `content` then holds its description
+ALTER TABLE clips ADD COLUMN attachment_filename TEXT;
+ALTER TABLE clips ADD COLUMN attachment_mime_type TEXT;
+ALTER TABLE clips ADD COLUMN attachment_size BIGINT;
+
+-- Attachment bytes, unless the server keeps them in a directory instead
+CREATE TABLE IF NOT EXISTS attachment_blobs (
+    clip_id TEXT PRIMARY KEY NOT NULL REFERENCES clips(id) ON DELETE CASCADE,
+    data BLOB NOT NULL
+);
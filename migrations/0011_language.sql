@@ -0,0 +1,2 @@
+-- Syntax used to highlight a clip, detected from its content when not given
+ALTER TABLE clips ADD COLUMN language TEXT;
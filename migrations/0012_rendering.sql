@@ -0,0 +1,3 @@
+-- How a clip is shown, existing clips keep being highlighted as code
+ALTER TABLE clips ADD COLUMN rendering TEXT NOT NULL DEFAULT 'code'
+    CHECK (rendering IN ('plain', 'code', 'markdown'));
@@ -0,0 +1,7 @@
+-- The current version of a clip and when it last changed, numbered after its revisions
+ALTER TABLE clips ADD COLUMN revision BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE clips ADD COLUMN updated_at DATETIME;
+
+UPDATE clips SET
+    revision = 1 + COALESCE((SELECT MAX(revision) FROM clip_revisions WHERE clip_id = clips.id), 0),
+    updated_at = (SELECT MAX(revised_at) FROM clip_revisions WHERE clip_id = clips.id);
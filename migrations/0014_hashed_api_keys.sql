@@ -0,0 +1,22 @@
+-- Keys are stored as a hash along with a visible prefix. Existing keys keep their raw value until
+-- they are first used and are hashed then, they keep every scope they had. Their prefix is a
+-- random placeholder until then, taking it from the key would publish part of it.
+ALTER TABLE api_keys ADD COLUMN prefix TEXT;
+ALTER TABLE api_keys ADD COLUMN key_hash TEXT;
+ALTER TABLE api_keys ADD COLUMN name TEXT NOT NULL DEFAULT '';
+ALTER TABLE api_keys ADD COLUMN scopes TEXT NOT NULL DEFAULT '';
+ALTER TABLE api_keys ADD COLUMN created_at DATETIME NOT NULL DEFAULT 0;
+ALTER TABLE api_keys ADD COLUMN expires_at DATETIME;
+ALTER TABLE api_keys ADD COLUMN last_used_at DATETIME;
+
+UPDATE api_keys SET
+    prefix = lower(hex(randomblob(6))),
+    scopes = CASE
+        WHEN admin THEN 'clip:read clip:write clip:delete admin'
+        ELSE 'clip:read clip:write clip:delete'
+    END,
+    created_at = strftime('%s', 'now');
+
+ALTER TABLE api_keys DROP COLUMN admin;
+
+CREATE UNIQUE INDEX IF NOT EXISTS api_keys_prefix ON api_keys (prefix);
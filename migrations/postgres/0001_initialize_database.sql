@@ -0,0 +1,68 @@
+-- The schema the SQLite migrations arrived at, times are stored as UTC without a time zone
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    api_key      BYTEA PRIMARY KEY,
+    prefix       TEXT UNIQUE,
+    key_hash     TEXT,
+    name         TEXT NOT NULL DEFAULT '',
+    scopes       TEXT NOT NULL DEFAULT '',
+    created_at   TIMESTAMP NOT NULL,
+    expires_at   TIMESTAMP,
+    last_used_at TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS clips
+(
+    id                   TEXT PRIMARY KEY NOT NULL,
+    short_code           TEXT UNIQUE NOT NULL,
+    content              TEXT NOT NULL,
+    title                TEXT,
+    created_at           TIMESTAMP NOT NULL,
+    expires_at           TIMESTAMP,
+    password             TEXT,
+    views                BIGINT NOT NULL DEFAULT 0,
+    max_views            BIGINT,
+    management_token     TEXT,
+    burn_after_read      BOOLEAN NOT NULL DEFAULT FALSE,
+    owner                BYTEA REFERENCES api_keys (api_key) ON DELETE SET NULL,
+    visibility           TEXT NOT NULL DEFAULT 'unlisted'
+        CHECK (visibility IN ('public', 'unlisted', 'private')),
+    attachment_filename  TEXT,
+    attachment_mime_type TEXT,
+    attachment_size      BIGINT,
+    language             TEXT,
+    rendering            TEXT NOT NULL DEFAULT 'code'
+        CHECK (rendering IN ('plain', 'code', 'markdown')),
+    revision             BIGINT NOT NULL DEFAULT 1,
+    updated_at           TIMESTAMP,
+    -- password-protected clips are never indexed, titles weigh more than content
+    search               TSVECTOR GENERATED ALWAYS AS (
+        CASE WHEN password IS NULL THEN
+            setweight(to_tsvector('simple', COALESCE(title, '')), 'A') ||
+            setweight(to_tsvector('simple', content), 'B')
+        END
+    ) STORED
+);
+
+CREATE INDEX IF NOT EXISTS clips_owner_created_at ON clips (owner, created_at, id);
+CREATE INDEX IF NOT EXISTS clips_owner_views ON clips (owner, views, id);
+CREATE INDEX IF NOT EXISTS clips_visibility_created_at ON clips (visibility, created_at, id);
+CREATE INDEX IF NOT EXISTS clips_visibility_views ON clips (visibility, views, id);
+CREATE INDEX IF NOT EXISTS clips_search ON clips USING GIN (search);
+
+CREATE TABLE IF NOT EXISTS clip_revisions
+(
+    clip_id    TEXT NOT NULL REFERENCES clips (id) ON DELETE CASCADE,
+    revision   BIGINT NOT NULL,
+    content    TEXT NOT NULL,
+    title      TEXT,
+    expires_at TIMESTAMP,
+    revised_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (clip_id, revision)
+);
+
+CREATE TABLE IF NOT EXISTS attachment_blobs
+(
+    clip_id TEXT PRIMARY KEY NOT NULL REFERENCES clips (id) ON DELETE CASCADE,
+    data    BYTEA NOT NULL
+);
@@ -1,8 +1,14 @@
-use clipshare::domain::clip::field::{Content, ExpiresAt, Password, ShortCode, Title};
-use clipshare::service::ask::{GetClip, NewClip, UpdateClip};
-use clipshare::web::api::{ApiKey, API_KEY_HEADER};
-use clipshare::Clip;
+use clipshare::domain::clip::field::{
+    Content, ExpiresAt, Language, ManagementToken, MaxViews, Password, Rendering, ShortCode, Title,
+    VanityCode, Visibility,
+};
+use clipshare::service::ask::{
+    ClipSort, ExpiryStatus, GetClip, ListScope, NewClip, Patch, PatchClip,
+};
+use clipshare::web::api::{RawApiKey, API_KEY_HEADER, MANAGEMENT_TOKEN_HEADER};
+use clipshare::web::{ClipPage, ClipView, SearchResult};
 use std::error::Error;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -20,24 +26,152 @@ enum Command {
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
 
-        #[structopt(short, long, help = "expiration date")]
+        #[structopt(
+            short,
+            long,
+            help = "expiration as a duration (10m, 1h, 7d), RFC 3339 timestamp or YYYY-MM-DD date"
+        )]
         expires_at: Option<ExpiresAt>,
 
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+
+        #[structopt(long, help = "number of views after which the clip expires")]
+        max_views: Option<MaxViews>,
+
+        #[structopt(long, help = "delete the clip after it is read once")]
+        burn_after_read: bool,
+
+        #[structopt(long, help = "custom short code instead of a generated one")]
+        short_code: Option<VanityCode>,
+
+        #[structopt(long, help = "public, unlisted or private")]
+        visibility: Option<Visibility>,
+
+        #[structopt(
+            long,
+            help = "language to highlight, detected from the content when omitted"
+        )]
+        language: Option<Language>,
+
+        #[structopt(long, help = "plain, code or markdown")]
+        rendering: Option<Rendering>,
     },
-    Update {
+    Upload {
+        #[structopt(parse(from_os_str), help = "file to share")]
+        file: PathBuf,
+
+        #[structopt(short, long, help = "description, defaults to the file name")]
+        description: Option<String>,
+
+        #[structopt(short, long, help = "password")]
+        password: Option<String>,
+
+        #[structopt(
+            short,
+            long,
+            help = "expiration as a duration (10m, 1h, 7d), RFC 3339 timestamp or YYYY-MM-DD date"
+        )]
+        expires_at: Option<String>,
+
+        #[structopt(short, long, help = "title")]
+        title: Option<String>,
+
+        #[structopt(long, help = "custom short code instead of a generated one")]
+        short_code: Option<String>,
+
+        #[structopt(long, help = "public, unlisted or private")]
+        visibility: Option<Visibility>,
+    },
+    Download {
         short_code: ShortCode,
-        clip: String,
+
+        #[structopt(
+            short,
+            long,
+            parse(from_os_str),
+            help = "defaults to the uploaded file name"
+        )]
+        output: Option<PathBuf>,
 
         #[structopt(short, long, help = "password")]
+        password: Option<String>,
+    },
+    Update {
+        short_code: ShortCode,
+
+        #[structopt(help = "new content, the current content is kept when omitted")]
+        clip: Option<String>,
+
+        #[structopt(long, help = "management token returned when the clip was created")]
+        token: Option<ManagementToken>,
+
+        #[structopt(short, long, help = "password", conflicts_with = "clear-password")]
         password: Option<Password>,
 
-        #[structopt(short, long, help = "expiration date")]
+        #[structopt(long, help = "remove the password")]
+        clear_password: bool,
+
+        #[structopt(long, help = "public, unlisted or private")]
+        visibility: Option<Visibility>,
+
+        #[structopt(long, help = "language to highlight, an empty value detects it again")]
+        language: Option<Language>,
+
+        #[structopt(long, help = "plain, code or markdown")]
+        rendering: Option<Rendering>,
+
+        #[structopt(
+            short,
+            long,
+            help = "expiration as a duration (10m, 1h, 7d), RFC 3339 timestamp or YYYY-MM-DD date",
+            conflicts_with = "clear-expires-at"
+        )]
         expires_at: Option<ExpiresAt>,
 
-        #[structopt(short, long, help = "title")]
+        #[structopt(long, help = "remove the expiration date")]
+        clear_expires_at: bool,
+
+        #[structopt(short, long, help = "title", conflicts_with = "clear-title")]
         title: Option<Title>,
+
+        #[structopt(long, help = "remove the title")]
+        clear_title: bool,
+    },
+    Delete {
+        short_code: ShortCode,
+
+        #[structopt(long, help = "management token returned when the clip was created")]
+        token: Option<ManagementToken>,
+    },
+    List {
+        #[structopt(long, help = "cursor printed with the previous page")]
+        cursor: Option<String>,
+
+        #[structopt(long, help = "number of clips per page, at most 100")]
+        limit: Option<u32>,
+
+        #[structopt(long, default_value = "created_at", help = "created_at or views")]
+        sort: ClipSort,
+
+        #[structopt(long, default_value = "all", help = "all, expired or unexpired")]
+        status: ExpiryStatus,
+
+        #[structopt(long, default_value = "mine", help = "mine or public")]
+        scope: ListScope,
+
+        #[structopt(long, help = "print the page as JSON")]
+        json: bool,
+    },
+    Search {
+        #[structopt(help = "words that have to appear in the title or content")]
+        query: String,
+
+        #[structopt(long, help = "number of results, at most 100")]
+        limit: Option<u32>,
+
+        #[structopt(long, help = "print the results as JSON")]
+        json: bool,
     },
 }
 
@@ -51,10 +185,10 @@ struct Opt {
     addr: String,
 
     #[structopt(long)]
-    api_key: ApiKey,
+    api_key: RawApiKey,
 }
 
-fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn get_clip(addr: &str, ask_svc: GetClip, api_key: RawApiKey) -> Result<ClipView, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
     let addr = format!("{}/api/clip/{}", addr, ask_svc.short_code.into_inner());
     let mut request = client.get(addr);
@@ -63,28 +197,217 @@ fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<d
         None => request,
     };
 
-    request = request.header(API_KEY_HEADER, api_key.to_base64());
+    request = request.header(API_KEY_HEADER, api_key.as_str());
     Ok(request.send()?.json()?)
 }
 
-fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn new_clip(addr: &str, ask_svc: NewClip, api_key: RawApiKey) -> Result<ClipView, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
     let addr = format!("{}/api/clip", addr);
     let mut request = client.post(addr);
 
-    request = request.header(API_KEY_HEADER, api_key.to_base64());
+    request = request.header(API_KEY_HEADER, api_key.as_str());
     Ok(request.json(&ask_svc).send()?.json()?)
 }
 
-fn update_clip(addr: &str, ask_svc: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn upload_clip(
+    addr: &str,
+    file: PathBuf,
+    fields: Vec<(&'static str, String)>,
+    api_key: RawApiKey,
+) -> Result<ClipView, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
-    let mut request = client.put(addr);
+    let addr = format!("{}/api/clip/upload", addr);
+    let form = fields
+        .into_iter()
+        .fold(
+            reqwest::blocking::multipart::Form::new(),
+            |form, (name, value)| form.text(name, value),
+        )
+        .file("file", file)?;
+
+    let request = client
+        .post(addr)
+        .header(API_KEY_HEADER, api_key.as_str())
+        .multipart(form);
+    Ok(request.send()?.error_for_status()?.json()?)
+}
 
-    request = request.header(API_KEY_HEADER, api_key.to_base64());
+/// returns the file along with the name it was uploaded as
+fn download_clip(
+    addr: &str,
+    ask_svc: GetClip,
+    api_key: RawApiKey,
+) -> Result<(Vec<u8>, Option<String>), Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!(
+        "{}/api/clip/{}/attachment",
+        addr,
+        ask_svc.short_code.into_inner()
+    );
+    let mut request = client.get(addr);
+    request = match ask_svc.password.into_inner() {
+        Some(password) => request.header(reqwest::header::COOKIE, format!("password={}", password)),
+        None => request,
+    };
+
+    request = request.header(API_KEY_HEADER, api_key.as_str());
+    let response = request.send()?.error_for_status()?;
+    let filename = response
+        .headers()
+        .get(reqwest::header::CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split("filename=\"").nth(1))
+        .and_then(|value| value.split('"').next())
+        .map(str::to_owned);
+    Ok((response.bytes()?.to_vec(), filename))
+}
+
+fn patch_clip(
+    addr: &str,
+    short_code: ShortCode,
+    ask_svc: PatchClip,
+    token: Option<ManagementToken>,
+    api_key: RawApiKey,
+) -> Result<ClipView, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}", addr, short_code.into_inner());
+    let mut request = client.patch(addr);
+
+    request = match token {
+        Some(token) => request.header(MANAGEMENT_TOKEN_HEADER, token.into_inner()),
+        None => request,
+    };
+    request = request.header(API_KEY_HEADER, api_key.as_str());
     Ok(request.json(&ask_svc).send()?.json()?)
 }
 
+fn delete_clip(
+    addr: &str,
+    short_code: ShortCode,
+    token: Option<ManagementToken>,
+    api_key: RawApiKey,
+) -> Result<bool, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}", addr, short_code.into_inner());
+    let mut request = client.delete(addr);
+
+    request = match token {
+        Some(token) => request.header(MANAGEMENT_TOKEN_HEADER, token.into_inner()),
+        None => request,
+    };
+    request = request.header(API_KEY_HEADER, api_key.as_str());
+
+    let response = request.send()?;
+    match response.status() {
+        reqwest::StatusCode::NOT_FOUND => Ok(false),
+        _ => {
+            response.error_for_status()?;
+            Ok(true)
+        }
+    }
+}
+
+fn list_clips(
+    addr: &str,
+    query: &[(&str, String)],
+    api_key: RawApiKey,
+) -> Result<ClipPage, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip", addr);
+    let request = client
+        .get(addr)
+        .query(query)
+        .header(API_KEY_HEADER, api_key.as_str());
+
+    Ok(request.send()?.error_for_status()?.json()?)
+}
+
+fn search_clips(
+    addr: &str,
+    query: &[(&str, String)],
+    api_key: RawApiKey,
+) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/search", addr);
+    let request = client
+        .get(addr)
+        .query(query)
+        .header(API_KEY_HEADER, api_key.as_str());
+
+    Ok(request.send()?.error_for_status()?.json()?)
+}
+
+/// turns the HTML snippet into terminal output, matches are printed in bold
+fn print_snippet(snippet: &str) {
+    let text = snippet
+        .replace("<mark>", "\x1b[1m")
+        .replace("</mark>", "\x1b[0m")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#x60;", "`")
+        .replace("&#x3D;", "=")
+        .replace("&amp;", "&");
+    for line in text.lines() {
+        println!("    {}", line);
+    }
+}
+
+fn print_table(page: &ClipPage) {
+    let format_time =
+        |time: &clipshare::Time| time.clone().into_inner().format("%F %R").to_string();
+    let rows: Vec<[String; 5]> = page
+        .clips
+        .iter()
+        .map(|clip| {
+            [
+                clip.short_code.clone(),
+                clip.title.clone().unwrap_or_default(),
+                format_time(&clip.created_at),
+                clip.expires_at
+                    .as_ref()
+                    .map(format_time)
+                    .unwrap_or_default(),
+                match clip.max_views {
+                    Some(max_views) => format!("{}/{}", clip.views, max_views),
+                    None => clip.views.to_string(),
+                },
+            ]
+        })
+        .collect();
+
+    let header = ["SHORT CODE", "TITLE", "CREATED", "EXPIRES", "VIEWS"].map(String::from);
+    let mut widths = header.clone().map(|column| column.chars().count());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+
+    if let Some(cursor) = &page.next_cursor {
+        println!("\nnext page: --cursor {}", cursor);
+    }
+}
+
+fn patch<T>(value: Option<T>, clear: bool) -> Patch<T> {
+    match value {
+        Some(value) => Patch::Set(value),
+        None if clear => Patch::Clear,
+        None => Patch::Keep,
+    }
+}
+
 fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
     match opt.command {
         Command::Get {
@@ -94,6 +417,7 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
             let req = GetClip {
                 password: Password::new(password.unwrap_or_default())?,
                 short_code,
+                caller: None,
             };
             let clip = get_clip(opt.addr.as_str(), req, opt.api_key)?;
             println!("{:#?}", clip);
@@ -104,42 +428,161 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
             password,
             expires_at,
             title,
+            max_views,
+            burn_after_read,
+            short_code,
+            visibility,
+            language,
+            rendering,
         } => {
             let req = NewClip {
                 content: Content::new(clip.as_str())?,
                 title: title.unwrap_or_default(),
                 exprires_at: expires_at.unwrap_or_default(),
                 password: password.unwrap_or_default(),
+                max_views: max_views.unwrap_or_default(),
+                burn_after_read,
+                short_code: short_code.unwrap_or_default(),
+                visibility: visibility.unwrap_or_default(),
+                language: language.unwrap_or_default(),
+                rendering: rendering.unwrap_or_default(),
             };
             let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
             println!("{:#?}", clip);
             Ok(())
         }
-        Command::Update {
-            clip,
+        Command::Upload {
+            file,
+            description,
             password,
             expires_at,
             title,
             short_code,
+            visibility,
         } => {
-            let password = password.unwrap_or_default();
-            let svc_req = GetClip {
-                password: password.clone(),
-                short_code: short_code.clone(),
-            };
-            let original_clip = get_clip(opt.addr.as_str(), svc_req, opt.api_key.clone())?;
-            let svc_req = UpdateClip {
-                content: Content::new(clip.as_str())?,
-                exprires_at: expires_at.unwrap_or(original_clip.expires_at),
-                title: title.unwrap_or(original_clip.title),
-                password,
+            let fields = [
+                ("content", description),
+                ("password", password),
+                ("expires_at", expires_at),
+                ("title", title),
+                ("short_code", short_code),
+                (
+                    "visibility",
+                    visibility.map(|visibility| visibility.to_string()),
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect();
+
+            let clip = upload_clip(opt.addr.as_str(), file, fields, opt.api_key)?;
+            println!("{:#?}", clip);
+            Ok(())
+        }
+        Command::Download {
+            short_code,
+            output,
+            password,
+        } => {
+            let req = GetClip {
+                password: Password::new(password.unwrap_or_default())?,
                 short_code,
+                caller: None,
+            };
+            let (data, filename) = download_clip(opt.addr.as_str(), req, opt.api_key)?;
+            // only the last component of the name chosen by the uploader is trusted
+            let output = output
+                .or_else(|| {
+                    filename
+                        .as_deref()
+                        .and_then(|name| std::path::Path::new(name).file_name())
+                        .map(PathBuf::from)
+                })
+                .unwrap_or_else(|| PathBuf::from("attachment"));
+            std::fs::write(&output, data)?;
+            println!("Saved to {}", output.display());
+            Ok(())
+        }
+        Command::Update {
+            short_code,
+            clip,
+            token,
+            password,
+            clear_password,
+            expires_at,
+            clear_expires_at,
+            title,
+            clear_title,
+            visibility,
+            language,
+            rendering,
+        } => {
+            let svc_req = PatchClip {
+                content: clip.as_deref().map(Content::new).transpose()?,
+                title: patch(title, clear_title),
+                expires_at: patch(expires_at, clear_expires_at),
+                password: patch(password, clear_password),
+                visibility,
+                language,
+                rendering,
             };
 
-            let clip = update_clip(opt.addr.as_str(), svc_req, opt.api_key)?;
+            let clip = patch_clip(opt.addr.as_str(), short_code, svc_req, token, opt.api_key)?;
             println!("{:#?}", clip);
             Ok(())
         }
+        Command::Delete { short_code, token } => {
+            if delete_clip(opt.addr.as_str(), short_code, token, opt.api_key)? {
+                println!("Clip deleted");
+            } else {
+                println!("Clip not found");
+            }
+            Ok(())
+        }
+        Command::List {
+            cursor,
+            limit,
+            sort,
+            status,
+            scope,
+            json,
+        } => {
+            let mut query = vec![
+                ("sort", sort.to_string()),
+                ("status", status.to_string()),
+                ("scope", scope.to_string()),
+            ];
+            query.extend(cursor.map(|cursor| ("cursor", cursor)));
+            query.extend(limit.map(|limit| ("limit", limit.to_string())));
+
+            let page = list_clips(opt.addr.as_str(), &query, opt.api_key)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&page)?);
+            } else {
+                print_table(&page);
+            }
+            Ok(())
+        }
+        Command::Search { query, limit, json } => {
+            let mut query = vec![("q", query)];
+            query.extend(limit.map(|limit| ("limit", limit.to_string())));
+
+            let results = search_clips(opt.addr.as_str(), &query, opt.api_key)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+            } else if results.is_empty() {
+                println!("No clips found");
+            } else {
+                for result in results {
+                    match result.title {
+                        Some(title) => println!("{}  {}", result.short_code, title),
+                        None => println!("{}", result.short_code),
+                    }
+                    print_snippet(&result.snippet);
+                }
+            }
+            Ok(())
+        }
     }
 }
 
@@ -0,0 +1,174 @@
+use clipshare::data::{query::DeletionStatus, query::RevocationStatus, AppDatabase, BlobStore};
+use clipshare::domain::clip::field::{ExpiresAt, ShortCode};
+use clipshare::service::{action, ask};
+use clipshare::web::api::Scope;
+use clipshare::ServiceError;
+use dotenv::dotenv;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+enum KeysCommand {
+    /// issues a key and prints it, the key cannot be shown again
+    Create {
+        #[structopt(short, long, default_value = "", help = "name to tell the key apart")]
+        name: String,
+
+        #[structopt(
+            short,
+            long = "scope",
+            help = "clip:read, clip:write, clip:delete or admin, may be repeated"
+        )]
+        scopes: Vec<Scope>,
+
+        #[structopt(
+            short,
+            long,
+            help = "expiration as a duration (10m, 1h, 7d), RFC 3339 timestamp or YYYY-MM-DD date"
+        )]
+        expires_at: Option<ExpiresAt>,
+    },
+    List,
+    Revoke {
+        prefix: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// manages API keys
+    Keys(KeysCommand),
+    /// deletes clips that expired or reached their view limit along with their files
+    Purge,
+    Delete {
+        short_code: ShortCode,
+    },
+    Stats,
+    /// applies pending migrations
+    Migrate,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "clipshare-admin", about = "Maintains a clipshare database")]
+struct Opt {
+    #[structopt(subcommand)]
+    command: Command,
+
+    #[structopt(
+        short,
+        long,
+        default_value = "sqlite:data.db",
+        help = "connection string to a sqlite or postgres database"
+    )]
+    connection_string: String,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "directory for uploaded files, they are stored in the database when not set"
+    )]
+    attachment_directory: Option<PathBuf>,
+}
+
+fn format_time(time: Option<clipshare::Time>) -> String {
+    time.map(|time| time.into_inner().format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+async fn run(opt: Opt) -> Result<(), ServiceError> {
+    let database = AppDatabase::new(&opt.connection_string).await?;
+    let pool = database.get_pool();
+    let storage = match opt.attachment_directory {
+        Some(dir) => BlobStore::Directory(dir),
+        None => BlobStore::Database,
+    };
+
+    match opt.command {
+        Command::Keys(KeysCommand::Create {
+            name,
+            scopes,
+            expires_at,
+        }) => {
+            let req = ask::NewApiKey {
+                name,
+                scopes: match scopes.is_empty() {
+                    true => Scope::defaults(),
+                    false => scopes,
+                },
+                expires_at: expires_at.unwrap_or_default(),
+            };
+            let api_key = action::generate_api_key(req, pool).await?;
+            println!("{}", api_key.as_str());
+        }
+        Command::Keys(KeysCommand::List) => {
+            println!(
+                "{:<12}  {:<20}  {:<40}  {:<16}  {:<16}  last used",
+                "prefix", "name", "scopes", "created", "expires"
+            );
+            for key in action::list_api_keys(pool).await? {
+                let scopes: Vec<&str> = key.scopes.iter().map(|scope| scope.as_str()).collect();
+                println!(
+                    "{:<12}  {:<20}  {:<40}  {:<16}  {:<16}  {}",
+                    key.prefix,
+                    key.name,
+                    scopes.join(" "),
+                    format_time(Some(key.created_at)),
+                    format_time(key.expires_at),
+                    format_time(key.last_used_at)
+                );
+            }
+        }
+        Command::Keys(KeysCommand::Revoke { prefix }) => {
+            match action::revoke_api_key(&prefix, pool).await? {
+                RevocationStatus::Revoked => println!("API key {} revoked", prefix),
+                RevocationStatus::NotFound => return Err(ServiceError::NotFound),
+            }
+        }
+        Command::Purge => {
+            let expired = action::delete_expires(pool).await?;
+            let exhausted = action::delete_exhausted(pool).await?;
+            let files = action::delete_orphaned_attachments(&storage, pool).await?;
+            println!(
+                "deleted {} expired and {} exhausted clips, {} attachments",
+                expired, exhausted, files
+            );
+        }
+        Command::Delete { short_code } => {
+            let auth = ask::Authorization::Admin;
+            match action::delete_clip(short_code.clone(), auth, &storage, pool).await? {
+                DeletionStatus::Deleted => println!("clip {} deleted", short_code.as_str()),
+                DeletionStatus::NotFound => return Err(ServiceError::NotFound),
+            }
+        }
+        Command::Stats => {
+            let stats = action::stats(pool).await?;
+            println!("clips:            {}", stats.clips);
+            println!("expired clips:    {}", stats.expired_clips);
+            println!("attachments:      {}", stats.attachments);
+            println!("attachment bytes: {}", stats.attachment_bytes);
+            println!("views:            {}", stats.views);
+            println!("API keys:         {}", stats.api_keys);
+        }
+        Command::Migrate => {
+            let applied = database.migrate().await?;
+            if applied.is_empty() {
+                println!("database is up to date");
+            }
+            for version in applied {
+                println!("applied migration {}", version);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    dotenv().ok();
+    let opt = Opt::from_args();
+
+    let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
+    if let Err(e) = rt.block_on(run(opt)) {
+        eprintln!("An error occurred: {}", e);
+        std::process::exit(1);
+    }
+}
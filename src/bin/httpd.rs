@@ -1,5 +1,10 @@
-use clipshare::data::AppDatabase;
+use clipshare::data::{AppDatabase, BlobStore};
+use clipshare::domain::clip::field::ShortCodeGenerator;
 use clipshare::domain::maintenance::Maintenance;
+use clipshare::domain::retention::RetentionPolicy;
+use clipshare::domain::time::parse_duration;
+use clipshare::service::{action, ask};
+use clipshare::web::api::Scope;
 use clipshare::web::renderer::Renderer;
 use clipshare::web::views::Views;
 use dotenv::dotenv;
@@ -11,12 +16,59 @@ use structopt::StructOpt;
 struct Opt {
     #[structopt(
         default_value = "sqlite:data.db",
-        help = "connection string to sqlite database"
+        help = "connection string to a sqlite or postgres database"
     )]
     connection_string: String,
 
     #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
     template_directory: PathBuf,
+
+    #[structopt(
+        long,
+        parse(try_from_str = parse_duration),
+        help = "expiry applied to clips created without one, e.g. 7d"
+    )]
+    default_ttl: Option<chrono::Duration>,
+
+    #[structopt(
+        long,
+        parse(try_from_str = parse_duration),
+        help = "longest a clip may be kept, e.g. 30d"
+    )]
+    max_ttl: Option<chrono::Duration>,
+
+    #[structopt(
+        long,
+        default_value = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789",
+        help = "characters used for generated short codes"
+    )]
+    short_code_alphabet: String,
+
+    #[structopt(long, default_value = "8", help = "length of generated short codes")]
+    short_code_length: usize,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "directory for uploaded files, they are stored in the database when not set"
+    )]
+    attachment_directory: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "10MiB",
+        help = "largest file that can be uploaded, e.g. 512KiB or 25MiB"
+    )]
+    max_upload_size: rocket::data::ByteUnit,
+
+    #[structopt(
+        long,
+        help = "issue an admin API key, print it and exit without starting the server"
+    )]
+    issue_admin_key: bool,
+
+    #[structopt(long, help = "start without applying pending database migrations")]
+    no_migrate: bool,
 }
 
 fn main() {
@@ -27,17 +79,61 @@ fn main() {
 
     let handle = rt.handle().clone();
     let renderer = Renderer::new(opt.template_directory.clone());
+    let retention =
+        RetentionPolicy::new(opt.default_ttl, opt.max_ttl).expect("invalid retention policy");
+    let short_codes = ShortCodeGenerator::new(&opt.short_code_alphabet, opt.short_code_length)
+        .expect("invalid short code settings");
+
+    let storage = match opt.attachment_directory {
+        Some(dir) => BlobStore::Directory(dir),
+        None => BlobStore::Database,
+    };
+
+    let database = rt
+        .block_on(async { AppDatabase::new(&opt.connection_string).await })
+        .expect("failed to open database");
+
+    // reported on stderr, so the output of --issue-admin-key is nothing but the key
+    if !opt.no_migrate {
+        let applied = rt
+            .block_on(async { database.migrate().await })
+            .expect("failed to migrate database");
+        for version in &applied {
+            eprintln!("Applied migration {}", version);
+        }
+    }
+    match rt.block_on(async { database.schema_version().await }) {
+        Ok(Some(version)) => eprintln!("Database schema at version {}", version),
+        Ok(None) => eprintln!("Database schema is empty, run without --no-migrate to create it"),
+        Err(e) => eprintln!("failed to read database schema version: {}", e),
+    }
 
-    let database = rt.block_on(async move { AppDatabase::new(&opt.connection_string).await });
+    // the first admin key has to be issued out of band, later ones can be issued over the API
+    if opt.issue_admin_key {
+        let req = ask::NewApiKey {
+            name: "admin".to_owned(),
+            scopes: vec![Scope::Admin],
+            expires_at: Default::default(),
+        };
+        let api_key = rt
+            .block_on(async { action::generate_api_key(req, database.get_pool()).await })
+            .expect("failed to issue admin key");
+        println!("{}", api_key.as_str());
+        return;
+    }
 
-    let views = Views::new(database.get_pool().clone(), handle.clone());
-    let maintenance = Maintenance::spawn(database.get_pool().clone(), handle.clone());
+    let views = Views::new(database.store(), handle.clone());
+    let maintenance = Maintenance::spawn(database.store(), storage.clone(), handle.clone());
 
     let config = clipshare::RocketConfig {
         renderer,
         database,
         views,
         maintenance,
+        retention,
+        short_codes,
+        storage,
+        max_upload_size: opt.max_upload_size,
     };
 
     rt.block_on(async move {
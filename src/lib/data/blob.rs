@@ -0,0 +1,119 @@
+use crate::data::{DataError, DatabasePool};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+/// Where the bytes of clip attachments are kept, keyed by clip id.
+///
+/// Blobs in the database are deleted along with their clip, files in a directory are
+/// removed by `delete_orphans` once their clip is gone.
+#[derive(Debug, Clone, Default)]
+pub enum BlobStore {
+    #[default]
+    Database,
+    Directory(PathBuf),
+}
+
+impl BlobStore {
+    pub async fn put(&self, clip_id: &str, data: &[u8], pool: &DatabasePool) -> Result<()> {
+        match self {
+            Self::Database => pool.put_attachment(clip_id, data).await?,
+            Self::Directory(dir) => {
+                tokio::fs::create_dir_all(dir).await?;
+                tokio::fs::write(dir.join(clip_id), data).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, clip_id: &str, pool: &DatabasePool) -> Result<Vec<u8>> {
+        match self {
+            Self::Database => pool.get_attachment(clip_id).await,
+            Self::Directory(dir) => Ok(tokio::fs::read(dir.join(clip_id)).await?),
+        }
+    }
+
+    pub async fn delete(&self, clip_id: &str, pool: &DatabasePool) -> Result<()> {
+        match self {
+            Self::Database => pool.delete_attachment(clip_id).await?,
+            Self::Directory(dir) => match tokio::fs::remove_file(dir.join(clip_id)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            },
+        }
+        Ok(())
+    }
+
+    /// removes files whose clip was deleted or expired, returning how many were removed
+    pub async fn delete_orphans(&self, pool: &DatabasePool) -> Result<u64> {
+        let dir = match self {
+            Self::Database => return Ok(0),
+            Self::Directory(dir) => dir,
+        };
+
+        // files are written after their clip was inserted, so listing them before reading
+        // the clip ids never mistakes a fresh upload for an orphan
+        let mut files = vec![];
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if let Ok(name) = entry.file_name().into_string() {
+                files.push(name);
+            }
+        }
+
+        let clip_ids: HashSet<String> = pool.attachment_clip_ids().await?.into_iter().collect();
+
+        let mut deleted = 0;
+        for name in files.into_iter().filter(|name| !clip_ids.contains(name)) {
+            tokio::fs::remove_file(dir.join(name)).await?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::test::new_store;
+    use crate::data::{BlobStore, DatabasePool};
+    use crate::test::async_runtime;
+
+    #[test]
+    fn directory_store_deletes_orphans() {
+        let rt = async_runtime();
+        let db = new_store(rt.handle());
+        let sqlite = db.pool();
+        let pool: &DatabasePool = &db;
+        let dir = std::env::temp_dir().join(format!("clipshare-{}", uuid::Uuid::new_v4()));
+        let store = BlobStore::Directory(dir.clone());
+
+        rt.block_on(async move {
+            sqlx::query(
+                "INSERT INTO clips (id, short_code, content, created_at, views, \
+                 attachment_filename, attachment_mime_type, attachment_size) \
+                 VALUES ('kept', 'kept', 'kept.txt', 0, 0, 'kept.txt', 'text/plain', 4)",
+            )
+            .execute(sqlite)
+            .await
+            .unwrap();
+
+            store.put("kept", b"kept", pool).await.unwrap();
+            store.put("deleted", b"gone", pool).await.unwrap();
+            assert_eq!(store.get("kept", pool).await.unwrap(), b"kept");
+
+            assert_eq!(store.delete_orphans(pool).await.unwrap(), 1);
+            assert!(store.get("kept", pool).await.is_ok());
+            assert!(store.get("deleted", pool).await.is_err());
+
+            store.delete("kept", pool).await.unwrap();
+            assert!(store.get("kept", pool).await.is_err());
+        });
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,49 +1,95 @@
+pub mod blob;
 pub mod model;
+pub mod postgres;
 pub mod query;
+pub mod sqlite;
+pub mod store;
+
+pub use blob::BlobStore;
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+pub use store::{ClipStore, ClipTransaction};
 
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
-use sqlx::Sqlite;
 use std::str::FromStr;
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataError {
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
-}
 
-pub type AppDatabase = Database<Sqlite>;
-pub type DatabasePool = sqlx::sqlite::SqlitePool;
-pub type Transaction<'t> = sqlx::Transaction<'t, Sqlite>;
-pub type AppDatabaseRow = sqlx::sqlite::SqliteRow;
-pub type AppQueryResult = sqlx::sqlite::SqliteQueryResult;
+    #[error("short code '{0}' is already taken")]
+    ShortCodeTaken(String),
 
-pub struct Database<D: sqlx::Database>(sqlx::Pool<D>);
+    #[error("no unused short code found after {0} attempts")]
+    ShortCodeExhausted(usize),
 
-impl Database<Sqlite> {
-    pub async fn new(connection_str: &str) -> Self {
-        let pool = sqlx::sqlite::SqlitePoolOptions::new()
-            .connect(connection_str)
-            .await;
+    #[error("attachment storage error: {0}")]
+    Storage(#[from] std::io::Error),
+
+    #[error("migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+}
 
-        match pool {
-            Ok(pool) => Self(pool),
-            Err(e) => {
-                eprintln!("{}\n", e);
-                eprintln!(
-                    "If the database has not yet been created, run: \n sqlx[.exe] database setup\n"
-                );
-                panic!("database connection error")
-            }
-        }
+pub type AppDatabase = Database;
+/// The store the service layer works with, whichever database backs it.
+pub type DatabasePool = dyn ClipStore;
+
+/// A connection to the database named by a connection string, `postgres://` and
+/// `postgresql://` connect to PostgreSQL, anything else to SQLite.
+#[derive(Clone)]
+pub struct Database(Arc<DatabasePool>);
+
+impl Database {
+    /// connects to the database, SQLite database files are created when missing
+    pub async fn new(connection_str: &str) -> Result<Self, DataError> {
+        let store: Arc<DatabasePool> = if is_postgres(connection_str) {
+            Arc::new(PostgresStore::connect(connection_str).await?)
+        } else {
+            Arc::new(SqliteStore::connect(connection_str).await?)
+        };
+        Ok(Self(store))
     }
 
     pub fn get_pool(&self) -> &DatabasePool {
-        &self.0
+        self.0.as_ref()
+    }
+
+    /// a handle to the store for tasks that outlive a request
+    pub fn store(&self) -> Arc<DatabasePool> {
+        self.0.clone()
+    }
+
+    /// applies pending migrations from the ones embedded at build time, returning their versions
+    pub async fn migrate(&self) -> Result<Vec<i64>, DataError> {
+        self.0.migrate().await
+    }
+
+    /// the newest migration applied to the database, if any
+    pub async fn schema_version(&self) -> Result<Option<i64>, DataError> {
+        self.0.schema_version().await
     }
 }
 
+impl From<SqliteStore> for Database {
+    fn from(store: SqliteStore) -> Self {
+        Self(Arc::new(store))
+    }
+}
+
+impl From<PostgresStore> for Database {
+    fn from(store: PostgresStore) -> Self {
+        Self(Arc::new(store))
+    }
+}
+
+fn is_postgres(connection_str: &str) -> bool {
+    connection_str.starts_with("postgres://") || connection_str.starts_with("postgresql://")
+}
+
 #[derive(Clone, Debug, From, Display, Deserialize, Serialize)]
 pub struct DbId(Uuid);
 
@@ -83,17 +129,82 @@ pub mod test {
     use tokio::runtime::Handle;
 
     pub fn new_db(handle: &Handle) -> AppDatabase {
-        use sqlx::migrate::Migrator;
-        use std::path::Path;
+        new_store(handle).into()
+    }
 
+    pub fn new_store(handle: &Handle) -> SqliteStore {
         handle.block_on(async move {
-            let db = Database::new(":memory:").await;
-            let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
+            // every connection to `:memory:` opens a separate database, so the
+            // pool is pinned to a single connection that is never recycled
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect(":memory:")
+                .await
+                .unwrap();
+            let store = SqliteStore::new(pool);
+            store.migrate().await.unwrap();
+            store
+        })
+    }
 
-            let pool = db.get_pool();
+    #[test]
+    fn migrations_apply_once() {
+        let rt = crate::test::async_runtime();
+        let db = new_db(rt.handle());
+
+        rt.block_on(async move {
+            let latest = db.schema_version().await.unwrap();
+            assert!(latest.is_some());
+            assert!(db.migrate().await.unwrap().is_empty());
+            assert_eq!(db.schema_version().await.unwrap(), latest);
+        });
+    }
 
-            migrator.run(pool).await.unwrap();
-            db
-        })
+    #[test]
+    fn schema_version_leaves_database_untouched() {
+        let rt = crate::test::async_runtime();
+
+        rt.block_on(async {
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect(":memory:")
+                .await
+                .unwrap();
+            let store = SqliteStore::new(pool);
+            assert_eq!(store.schema_version().await.unwrap(), None);
+
+            let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master")
+                .fetch_one(store.pool())
+                .await
+                .unwrap();
+            assert_eq!(tables, 0);
+        });
+    }
+
+    #[test]
+    fn new_creates_missing_database() {
+        let path = std::env::temp_dir().join(format!("clipshare-{}.db", DbId::new()));
+        let rt = crate::test::async_runtime();
+
+        rt.block_on(async {
+            let db = Database::new(&format!("sqlite:{}", path.display()))
+                .await
+                .unwrap();
+            assert!(!db.migrate().await.unwrap().is_empty());
+        });
+        assert!(path.exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn picks_store_from_connection_string() {
+        assert!(super::is_postgres("postgres://localhost/clipshare"));
+        assert!(super::is_postgres("postgresql://user@localhost/clipshare"));
+        assert!(!super::is_postgres("sqlite:data.db"));
+        assert!(!super::is_postgres("data.db"));
     }
 }
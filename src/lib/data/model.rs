@@ -1,4 +1,5 @@
 use crate::data::DbId;
+use crate::domain::clip::field::{Attachment, ManagementToken};
 use crate::{ClipError, ShortCode, Time};
 use chrono::{NaiveDateTime, Utc};
 use std::convert::TryFrom;
@@ -13,6 +14,18 @@ pub struct Clip {
     pub(in crate::data) expires_at: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) views: i64,
+    pub(in crate::data) max_views: Option<i64>,
+    pub(in crate::data) management_token: Option<String>,
+    pub(in crate::data) burn_after_read: bool,
+    pub(in crate::data) owner: Option<Vec<u8>>,
+    pub(in crate::data) visibility: String,
+    pub(in crate::data) attachment_filename: Option<String>,
+    pub(in crate::data) attachment_mime_type: Option<String>,
+    pub(in crate::data) attachment_size: Option<i64>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) rendering: String,
+    pub(in crate::data) revision: i64,
+    pub(in crate::data) updated_at: Option<NaiveDateTime>,
 }
 
 impl TryFrom<Clip> for crate::domain::Clip {
@@ -22,15 +35,59 @@ impl TryFrom<Clip> for crate::domain::Clip {
         use crate::domain::clip::field;
         use std::str::FromStr;
 
+        let attachment = match (clip.attachment_filename, clip.attachment_size) {
+            (Some(filename), Some(size)) => Some(field::Attachment::new(
+                &filename,
+                clip.attachment_mime_type.as_deref(),
+                u64::try_from(size)?,
+            )?),
+            _ => None,
+        };
+
         Ok(Self {
             id: field::Id::new(DbId::from_str(clip.id.as_str())?),
             short_code: field::ShortCode::from(clip.short_code),
             content: field::Content::new(clip.content.as_str())?,
+            attachment,
+            language: field::Language::from_str(clip.language.as_deref().unwrap_or_default())?,
+            rendering: field::Rendering::from_str(&clip.rendering)?,
             title: field::Title::new(clip.title),
             created_at: field::CreatedAt::new(Time::from_naive_utc(clip.created_at)),
             expires_at: field::ExpiresAt::new(clip.expires_at.map(Time::from_naive_utc)),
-            password: field::Password::new(clip.password.unwrap_or_default())?,
+            password: field::HashedPassword::new(clip.password),
             views: field::Views::new(u64::try_from(clip.views)?),
+            max_views: field::MaxViews::new(clip.max_views.map(u64::try_from).transpose()?)?,
+            management_token: field::ManagementTokenHash::new(clip.management_token),
+            burn_after_read: clip.burn_after_read,
+            owner: field::Owner::new(clip.owner),
+            visibility: field::Visibility::from_str(&clip.visibility)?,
+            revision: u64::try_from(clip.revision)?,
+            updated_at: clip.updated_at.map(Time::from_naive_utc),
+        })
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Revision {
+    pub(in crate::data) revision: i64,
+    pub(in crate::data) content: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+    pub(in crate::data) revised_at: NaiveDateTime,
+}
+
+impl TryFrom<Revision> for crate::domain::Revision {
+    type Error = ClipError;
+
+    fn try_from(revision: Revision) -> Result<Self, Self::Error> {
+        use crate::domain::clip::field;
+
+        Ok(Self {
+            revision: u64::try_from(revision.revision)?,
+            content: field::Content::new(revision.content.as_str())?,
+            title: field::Title::new(revision.title),
+            expires_at: field::ExpiresAt::new(revision.expires_at.map(Time::from_naive_utc)),
+            revised_at: Time::from_naive_utc(revision.revised_at),
         })
     }
 }
@@ -63,25 +120,73 @@ impl From<String> for GetClip {
 
 pub struct NewClip {
     pub(in crate::data) id: String,
-    pub(in crate::data) short_code: String,
+    /// generated on insert when not set
+    pub(in crate::data) short_code: Option<String>,
     pub(in crate::data) content: String,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) created_at: i64,
     pub(in crate::data) expires_at: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) max_views: Option<i64>,
+    pub(in crate::data) management_token: Option<String>,
+    pub(in crate::data) burn_after_read: bool,
+    pub(in crate::data) owner: Option<Vec<u8>>,
+    pub(in crate::data) visibility: String,
+    pub(in crate::data) attachment_filename: Option<String>,
+    pub(in crate::data) attachment_mime_type: Option<String>,
+    pub(in crate::data) attachment_size: Option<i64>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) rendering: String,
 }
 
-impl From<crate::service::ask::NewClip> for NewClip {
-    fn from(req: crate::service::ask::NewClip) -> Self {
+impl NewClip {
+    pub fn with_management_token(self, token: &ManagementToken) -> Self {
         Self {
+            management_token: token.hash().into_inner(),
+            ..self
+        }
+    }
+
+    pub fn with_owner(self, owner: Option<&crate::web::api::ApiKey>) -> Self {
+        Self {
+            owner: owner.map(|api_key| api_key.clone().into_inner()),
+            ..self
+        }
+    }
+
+    pub fn with_attachment(self, attachment: &Attachment) -> Result<Self, ClipError> {
+        Ok(Self {
+            attachment_filename: Some(attachment.filename().to_owned()),
+            attachment_mime_type: Some(attachment.mime_type().to_owned()),
+            attachment_size: Some(i64::try_from(attachment.size())?),
+            ..self
+        })
+    }
+}
+
+impl TryFrom<crate::service::ask::NewClip> for NewClip {
+    type Error = ClipError;
+
+    fn try_from(req: crate::service::ask::NewClip) -> Result<Self, Self::Error> {
+        Ok(Self {
             id: DbId::new().into(),
             content: req.content.into_inner(),
             title: req.title.into_inner(),
             expires_at: req.exprires_at.into_inner().map(|time| time.timestamp()),
-            password: req.password.into_inner(),
-            short_code: ShortCode::default().into(),
+            password: req.password.hash()?.into_inner(),
+            short_code: req.short_code.into_inner().map(ShortCode::into_inner),
             created_at: Utc::now().timestamp(),
-        }
+            max_views: req.max_views.into_inner().map(i64::try_from).transpose()?,
+            management_token: None,
+            burn_after_read: req.burn_after_read,
+            owner: None,
+            visibility: req.visibility.as_str().to_owned(),
+            attachment_filename: None,
+            attachment_mime_type: None,
+            attachment_size: None,
+            language: req.language.into_inner(),
+            rendering: req.rendering.as_str().to_owned(),
+        })
     }
 }
 
@@ -91,16 +196,295 @@ pub struct UpdateClip {
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) expires_at: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) rendering: String,
 }
 
-impl From<crate::service::ask::UpdateClip> for UpdateClip {
-    fn from(req: crate::service::ask::UpdateClip) -> Self {
-        Self {
+impl TryFrom<crate::service::ask::UpdateClip> for UpdateClip {
+    type Error = ClipError;
+
+    fn try_from(req: crate::service::ask::UpdateClip) -> Result<Self, Self::Error> {
+        Ok(Self {
             content: req.content.into_inner(),
             title: req.title.into_inner(),
             expires_at: req.exprires_at.into_inner().map(|time| time.timestamp()),
-            password: req.password.into_inner(),
-            short_code: ShortCode::default().into(),
+            password: req.password.hash()?.into_inner(),
+            short_code: req.short_code.into_inner(),
+            language: req.language.into_inner(),
+            rendering: req.rendering.as_str().to_owned(),
+        })
+    }
+}
+
+pub struct PatchClip {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) content: Option<String>,
+    pub(in crate::data) set_title: bool,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) set_expires_at: bool,
+    pub(in crate::data) expires_at: Option<i64>,
+    pub(in crate::data) set_password: bool,
+    pub(in crate::data) password: Option<String>,
+    pub(in crate::data) visibility: Option<String>,
+    pub(in crate::data) set_language: bool,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) rendering: Option<String>,
+}
+
+impl TryFrom<(ShortCode, crate::service::ask::PatchClip)> for PatchClip {
+    type Error = ClipError;
+
+    fn try_from(
+        (short_code, req): (ShortCode, crate::service::ask::PatchClip),
+    ) -> Result<Self, Self::Error> {
+        use crate::service::ask::Patch;
+
+        let (set_title, title) = match req.title {
+            Patch::Keep => (false, None),
+            Patch::Clear => (true, None),
+            Patch::Set(title) => (true, title.into_inner()),
+        };
+        let (set_expires_at, expires_at) = match req.expires_at {
+            Patch::Keep => (false, None),
+            Patch::Clear => (true, None),
+            Patch::Set(expires_at) => (true, expires_at.into_inner().map(|time| time.timestamp())),
+        };
+        let (set_password, password) = match req.password {
+            Patch::Keep => (false, None),
+            Patch::Clear => (true, None),
+            Patch::Set(password) => (true, password.hash()?.into_inner()),
+        };
+
+        Ok(Self {
+            short_code: short_code.into_inner(),
+            content: req.content.map(|content| content.into_inner()),
+            set_title,
+            title,
+            set_expires_at,
+            expires_at,
+            set_password,
+            password,
+            visibility: req
+                .visibility
+                .map(|visibility| visibility.as_str().to_owned()),
+            set_language: req.language.is_some(),
+            language: req.language.and_then(|language| language.into_inner()),
+            rendering: req.rendering.map(|rendering| rendering.as_str().to_owned()),
+        })
+    }
+}
+
+pub struct ListClips {
+    pub(in crate::data) owner: Vec<u8>,
+    pub(in crate::data) sort: crate::service::ask::ClipSort,
+    pub(in crate::data) status: crate::service::ask::ExpiryStatus,
+    pub(in crate::data) scope: crate::service::ask::ListScope,
+    pub(in crate::data) after: Option<(i64, String)>,
+    pub(in crate::data) limit: i64,
+}
+
+impl From<(&crate::web::api::ApiKey, crate::service::ask::ListClips)> for ListClips {
+    fn from((owner, req): (&crate::web::api::ApiKey, crate::service::ask::ListClips)) -> Self {
+        Self {
+            owner: owner.clone().into_inner(),
+            limit: i64::from(req.page_size()),
+            sort: req.sort,
+            status: req.status,
+            scope: req.scope,
+            // a cursor from a listing in another order points nowhere meaningful
+            after: req
+                .cursor
+                .filter(|cursor| cursor.sort == req.sort)
+                .map(|cursor| (cursor.value, cursor.id)),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct SearchHit {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) created_at: NaiveDateTime,
+    pub(in crate::data) snippet: String,
+    pub(in crate::data) rank: f64,
+}
+
+impl TryFrom<SearchHit> for crate::domain::SearchHit {
+    type Error = ClipError;
+
+    fn try_from(hit: SearchHit) -> Result<Self, Self::Error> {
+        use crate::domain::clip::field;
+
+        Ok(Self {
+            short_code: field::ShortCode::from(hit.short_code),
+            title: field::Title::new(hit.title),
+            created_at: field::CreatedAt::new(Time::from_naive_utc(hit.created_at)),
+            snippet: hit.snippet,
+            rank: hit.rank,
+        })
+    }
+}
+
+pub struct SearchClips {
+    /// an FTS5 expression for SQLite
+    pub(in crate::data) query: String,
+    /// the words searched for as entered, for PostgreSQL
+    pub(in crate::data) terms: String,
+    pub(in crate::data) caller: Vec<u8>,
+    pub(in crate::data) limit: i64,
+}
+
+impl From<(&crate::web::api::ApiKey, crate::service::ask::SearchClips)> for SearchClips {
+    fn from((caller, req): (&crate::web::api::ApiKey, crate::service::ask::SearchClips)) -> Self {
+        Self {
+            query: req.match_expression(),
+            terms: req.query.clone(),
+            caller: caller.clone().into_inner(),
+            limit: i64::from(req.page_size()),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKey {
+    pub(in crate::data) api_key: Vec<u8>,
+    pub(in crate::data) prefix: String,
+    /// missing for keys issued before keys were hashed, `api_key` holds the key itself then
+    pub(in crate::data) key_hash: Option<String>,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+}
+
+impl ApiKey {
+    pub fn id(&self) -> &[u8] {
+        self.api_key.as_slice()
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// the key as `upgrade_api_key` left it
+    pub fn upgraded(self, api_key: Vec<u8>, prefix: String) -> Self {
+        Self {
+            api_key,
+            prefix,
+            ..self
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.key_hash.is_none()
+    }
+
+    /// compares in constant time, so the time taken does not reveal how much of a key matched
+    pub fn verify(&self, raw_key: &crate::web::api::RawApiKey) -> bool {
+        use subtle::ConstantTimeEq;
+
+        match &self.key_hash {
+            Some(hash) => hash.as_bytes().ct_eq(raw_key.hash().as_bytes()).into(),
+            None => raw_key
+                .legacy_bytes()
+                .is_some_and(|bytes| bytes.ct_eq(&self.api_key).into()),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    }
+}
+
+impl From<ApiKey> for crate::web::api::ApiKey {
+    fn from(api_key: ApiKey) -> Self {
+        let scopes = parse_scopes(&api_key.scopes);
+        Self::new(api_key.api_key, api_key.prefix, scopes)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKeyDetails {
+    pub(in crate::data) prefix: String,
+    pub(in crate::data) name: String,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) created_at: NaiveDateTime,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+    pub(in crate::data) last_used_at: Option<NaiveDateTime>,
+}
+
+impl From<ApiKeyDetails> for crate::web::api::ApiKeyDetails {
+    fn from(details: ApiKeyDetails) -> Self {
+        Self {
+            scopes: parse_scopes(&details.scopes),
+            prefix: details.prefix,
+            name: details.name,
+            created_at: Time::from_naive_utc(details.created_at),
+            expires_at: details.expires_at.map(Time::from_naive_utc),
+            last_used_at: details.last_used_at.map(Time::from_naive_utc),
+        }
+    }
+}
+
+/// scopes this version does not know are dropped rather than granted
+fn parse_scopes(scopes: &str) -> Vec<crate::web::api::Scope> {
+    use std::str::FromStr;
+
+    scopes
+        .split_whitespace()
+        .filter_map(|scope| crate::web::api::Scope::from_str(scope).ok())
+        .collect()
+}
+
+pub struct NewApiKey {
+    pub(in crate::data) api_key: Vec<u8>,
+    pub(in crate::data) prefix: String,
+    pub(in crate::data) key_hash: String,
+    pub(in crate::data) name: String,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) created_at: i64,
+    pub(in crate::data) expires_at: Option<i64>,
+}
+
+impl From<(&crate::web::api::RawApiKey, crate::service::ask::NewApiKey)> for NewApiKey {
+    fn from((raw_key, req): (&crate::web::api::RawApiKey, crate::service::ask::NewApiKey)) -> Self {
+        Self {
+            api_key: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            prefix: raw_key.prefix(),
+            key_hash: raw_key.hash(),
+            name: req.name,
+            scopes: req
+                .scopes
+                .iter()
+                .map(|scope| scope.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+            created_at: Utc::now().timestamp(),
+            expires_at: req.expires_at.into_inner().map(|time| time.timestamp()),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Stats {
+    pub(in crate::data) clips: i64,
+    pub(in crate::data) expired_clips: i64,
+    pub(in crate::data) attachments: i64,
+    pub(in crate::data) attachment_bytes: i64,
+    pub(in crate::data) views: i64,
+    pub(in crate::data) api_keys: i64,
+}
+
+impl From<Stats> for crate::domain::Stats {
+    fn from(stats: Stats) -> Self {
+        // counts are never negative
+        let count = |n: i64| u64::try_from(n).unwrap_or_default();
+        Self {
+            clips: count(stats.clips),
+            expired_clips: count(stats.expired_clips),
+            attachments: count(stats.attachments),
+            attachment_bytes: count(stats.attachment_bytes),
+            views: count(stats.views),
+            api_keys: count(stats.api_keys),
         }
     }
 }
@@ -0,0 +1,648 @@
+use super::model;
+use super::query::{DeletionStatus, RevocationStatus};
+use super::store::{ClipStore, ClipTransaction};
+use super::DataError;
+use crate::domain::clip::field::ShortCodeGenerator;
+use crate::ShortCode;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres};
+
+type Result<T> = std::result::Result<T, DataError>;
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// how often a generated short code is redrawn after colliding with an existing clip
+const SHORT_CODE_ATTEMPTS: usize = 10;
+
+/// the columns of `model::Clip`, the search index is left out
+const CLIP_COLUMNS: &str = "id, short_code, content, title, created_at, expires_at, password, \
+    views, max_views, management_token, burn_after_read, owner, visibility, attachment_filename, \
+    attachment_mime_type, attachment_size, language, rendering, revision, updated_at";
+
+/// Clips in a PostgreSQL database.
+///
+/// The compile time checked queries are bound to SQLite, so the queries here are checked by the
+/// integration tests in `tests/postgres.rs` instead. Times are written as UTC timestamps where
+/// SQLite keeps seconds since the epoch.
+#[derive(Debug, Clone)]
+pub struct PostgresStore(PgPool);
+
+impl PostgresStore {
+    pub async fn connect(connection_str: &str) -> Result<Self> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect(connection_str)
+            .await?;
+        Ok(Self(pool))
+    }
+
+    pub fn new(pool: PgPool) -> Self {
+        Self(pool)
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.0
+    }
+}
+
+fn time(timestamp: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap_or_default()
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+async fn get_clip<'c, E: PgExecutor<'c>>(
+    model: model::GetClip,
+    executor: E,
+) -> Result<model::Clip> {
+    let sql = format!("SELECT {} FROM clips WHERE short_code = $1", CLIP_COLUMNS);
+    Ok(sqlx::query_as::<_, model::Clip>(&sql)
+        .bind(model.short_code)
+        .fetch_one(executor)
+        .await?)
+}
+
+async fn update_password<'c, E: PgExecutor<'c>>(
+    short_code: &ShortCode,
+    password: Option<String>,
+    executor: E,
+) -> Result<()> {
+    sqlx::query("UPDATE clips SET password = $1 WHERE short_code = $2")
+        .bind(password)
+        .bind(short_code.as_str())
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+async fn consume_view<'c, E: PgExecutor<'c>>(short_code: &ShortCode, executor: E) -> Result<bool> {
+    Ok(sqlx::query(
+        "UPDATE clips SET views = views + 1 WHERE short_code = $1 AND views < max_views",
+    )
+    .bind(short_code.as_str())
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected() > 0)?)
+}
+
+async fn delete_clip<'c, E: PgExecutor<'c>>(
+    short_code: &ShortCode,
+    executor: E,
+) -> Result<DeletionStatus> {
+    Ok(sqlx::query("DELETE FROM clips WHERE short_code = $1")
+        .bind(short_code.as_str())
+        .execute(executor)
+        .await
+        .map(|result| match result.rows_affected() {
+            0 => DeletionStatus::NotFound,
+            _ => DeletionStatus::Deleted,
+        })?)
+}
+
+/// copies the current content, title and expiry of a clip into its history
+async fn record_revision<'c, E: PgExecutor<'c>>(short_code: &str, executor: E) -> Result<()> {
+    sqlx::query(
+        r#"INSERT INTO clip_revisions (clip_id, revision, content, title, expires_at, revised_at)
+        SELECT
+            id,
+            COALESCE((SELECT MAX(revision) FROM clip_revisions WHERE clip_id = clips.id), 0) + 1,
+            content,
+            title,
+            expires_at,
+            $1
+        FROM clips WHERE short_code = $2"#,
+    )
+    .bind(now())
+    .bind(short_code)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+fn is_short_code_collision(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => {
+            e.is_unique_violation() && e.constraint() == Some("clips_short_code_key")
+        }
+        _ => false,
+    }
+}
+
+async fn applied_migrations(pool: &PgPool) -> Result<Vec<i64>> {
+    use sqlx::migrate::Migrate;
+
+    let mut conn = pool.acquire().await?;
+    // only `migrate` may create the table, a database opened without migrating stays untouched
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(&mut *conn)
+        .await?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+#[rocket::async_trait]
+impl ClipStore for PostgresStore {
+    async fn begin(&self) -> Result<Box<dyn ClipTransaction>> {
+        Ok(Box::new(self.0.begin().await?))
+    }
+
+    async fn increase_views(&self, short_code: &ShortCode, views: u32) -> Result<()> {
+        sqlx::query("UPDATE clips SET views = views + $1 WHERE short_code = $2")
+            .bind(i64::from(views))
+            .bind(short_code.as_str())
+            .execute(&self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip> {
+        get_clip(model, &self.0).await
+    }
+
+    async fn new_clip(
+        &self,
+        model: model::NewClip,
+        short_codes: &ShortCodeGenerator,
+    ) -> Result<model::Clip> {
+        for _ in 0..SHORT_CODE_ATTEMPTS {
+            let short_code = match &model.short_code {
+                Some(short_code) => short_code.clone(),
+                None => short_codes.generate().into_inner(),
+            };
+
+            let inserted = sqlx::query(
+                r#"INSERT INTO clips (
+                    id, short_code, content, title, created_at, expires_at, password, views,
+                    max_views, management_token, burn_after_read, owner, visibility,
+                    attachment_filename, attachment_mime_type, attachment_size, language, rendering
+                ) VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, 0, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17
+                )"#,
+            )
+            .bind(&model.id)
+            .bind(&short_code)
+            .bind(&model.content)
+            .bind(&model.title)
+            .bind(time(model.created_at))
+            .bind(model.expires_at.map(time))
+            .bind(&model.password)
+            .bind(model.max_views)
+            .bind(&model.management_token)
+            .bind(model.burn_after_read)
+            .bind(&model.owner)
+            .bind(&model.visibility)
+            .bind(&model.attachment_filename)
+            .bind(&model.attachment_mime_type)
+            .bind(model.attachment_size)
+            .bind(&model.language)
+            .bind(&model.rendering)
+            .execute(&self.0)
+            .await;
+
+            match inserted {
+                Ok(_) => return get_clip(short_code.into(), &self.0).await,
+                Err(e) if is_short_code_collision(&e) => match model.short_code {
+                    Some(short_code) => return Err(DataError::ShortCodeTaken(short_code)),
+                    None => continue,
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(DataError::ShortCodeExhausted(SHORT_CODE_ATTEMPTS))
+    }
+
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip> {
+        let mut transaction = self.0.begin().await?;
+
+        record_revision(&model.short_code, &mut *transaction).await?;
+        let result = sqlx::query(
+            r#"UPDATE clips SET
+                revision = revision + 1,
+                updated_at = $1,
+                content = $2,
+                expires_at = $3,
+                password = $4,
+                title = $5,
+                language = $6,
+                rendering = $7
+            WHERE short_code = $8"#,
+        )
+        .bind(now())
+        .bind(&model.content)
+        .bind(model.expires_at.map(time))
+        .bind(&model.password)
+        .bind(&model.title)
+        .bind(&model.language)
+        .bind(&model.rendering)
+        .bind(&model.short_code)
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        let clip = get_clip(model.short_code.into(), &mut *transaction).await?;
+        transaction.commit().await?;
+        Ok(clip)
+    }
+
+    async fn patch_clip(&self, model: model::PatchClip) -> Result<model::Clip> {
+        let mut transaction = self.0.begin().await?;
+
+        // a password change alone does not produce a new version of the clip
+        let revised = model.content.is_some() || model.set_title || model.set_expires_at;
+        if revised {
+            record_revision(&model.short_code, &mut *transaction).await?;
+        }
+        let result = sqlx::query(
+            r#"UPDATE clips SET
+                revision = revision + CASE WHEN $1 THEN 1 ELSE 0 END,
+                updated_at = $2,
+                content = COALESCE($3, content),
+                title = CASE WHEN $4 THEN $5 ELSE title END,
+                expires_at = CASE WHEN $6 THEN $7 ELSE expires_at END,
+                password = CASE WHEN $8 THEN $9 ELSE password END,
+                visibility = COALESCE($10, visibility),
+                language = CASE WHEN $11 THEN $12 ELSE language END,
+                rendering = COALESCE($13, rendering)
+            WHERE short_code = $14"#,
+        )
+        .bind(revised)
+        .bind(now())
+        .bind(&model.content)
+        .bind(model.set_title)
+        .bind(&model.title)
+        .bind(model.set_expires_at)
+        .bind(model.expires_at.map(time))
+        .bind(model.set_password)
+        .bind(&model.password)
+        .bind(&model.visibility)
+        .bind(model.set_language)
+        .bind(&model.language)
+        .bind(&model.rendering)
+        .bind(&model.short_code)
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        let clip = get_clip(model.short_code.into(), &mut *transaction).await?;
+        transaction.commit().await?;
+        Ok(clip)
+    }
+
+    async fn delete_clip(&self, short_code: &ShortCode) -> Result<DeletionStatus> {
+        delete_clip(short_code, &self.0).await
+    }
+
+    async fn get_revisions(&self, short_code: &ShortCode) -> Result<Vec<model::Revision>> {
+        Ok(sqlx::query_as::<_, model::Revision>(
+            r#"SELECT r.revision, r.content, r.title, r.expires_at, r.revised_at
+            FROM clip_revisions r JOIN clips c ON c.id = r.clip_id
+            WHERE c.short_code = $1
+            ORDER BY r.revision"#,
+        )
+        .bind(short_code.as_str())
+        .fetch_all(&self.0)
+        .await?)
+    }
+
+    async fn get_revision(&self, short_code: &ShortCode, revision: i64) -> Result<model::Revision> {
+        Ok(sqlx::query_as::<_, model::Revision>(
+            r#"SELECT r.revision, r.content, r.title, r.expires_at, r.revised_at
+            FROM clip_revisions r JOIN clips c ON c.id = r.clip_id
+            WHERE c.short_code = $1 AND r.revision = $2"#,
+        )
+        .bind(short_code.as_str())
+        .bind(revision)
+        .fetch_one(&self.0)
+        .await?)
+    }
+
+    /// one page of clips like `query::list_clips`, with the cursor value of `created_at` being
+    /// seconds since the epoch
+    async fn list_clips(&self, model: model::ListClips) -> Result<Vec<model::Clip>> {
+        use crate::service::ask::{ClipSort, ExpiryStatus, ListScope};
+
+        let mut params = 0;
+        let mut param = || {
+            params += 1;
+            format!("${}", params)
+        };
+        let column = match model.sort {
+            ClipSort::CreatedAt => "created_at",
+            ClipSort::Views => "views",
+        };
+        let scope = match model.scope {
+            ListScope::Mine => format!("owner = {}", param()),
+            ListScope::Public => "visibility = 'public'".to_owned(),
+        };
+        let status = match model.status {
+            ExpiryStatus::All => String::new(),
+            ExpiryStatus::Expired => {
+                format!("AND expires_at IS NOT NULL AND expires_at <= {}", param())
+            }
+            ExpiryStatus::Unexpired => {
+                format!("AND (expires_at IS NULL OR expires_at > {})", param())
+            }
+        };
+        let after = match model.after {
+            Some(_) => {
+                let (before, equal, id) = (param(), param(), param());
+                format!("AND ({column} < {before} OR ({column} = {equal} AND id < {id}))")
+            }
+            None => String::new(),
+        };
+        let sql = format!(
+            "SELECT {CLIP_COLUMNS} FROM clips WHERE {scope} {status} {after} \
+            ORDER BY {column} DESC, id DESC LIMIT {}",
+            param()
+        );
+
+        let mut query = sqlx::query_as::<_, model::Clip>(&sql);
+        if model.scope == ListScope::Mine {
+            query = query.bind(model.owner);
+        }
+        if model.status != ExpiryStatus::All {
+            query = query.bind(now());
+        }
+        if let Some((value, id)) = model.after {
+            query = match model.sort {
+                ClipSort::CreatedAt => query.bind(time(value)).bind(time(value)),
+                ClipSort::Views => query.bind(value).bind(value),
+            };
+            query = query.bind(id);
+        }
+
+        Ok(query.bind(model.limit + 1).fetch_all(&self.0).await?)
+    }
+
+    /// ranked matches like `query::search_clips`, the rank is negated so lower is still better
+    async fn search_clips(&self, model: model::SearchClips) -> Result<Vec<model::SearchHit>> {
+        use crate::domain::clip::{SNIPPET_MATCH_END, SNIPPET_MATCH_START};
+
+        let headline = format!(
+            "StartSel={}, StopSel={}, MaxFragments=1, MaxWords=16, MinWords=4",
+            SNIPPET_MATCH_START, SNIPPET_MATCH_END
+        );
+        Ok(sqlx::query_as::<_, model::SearchHit>(
+            r#"SELECT
+                c.short_code,
+                c.title,
+                c.created_at,
+                ts_headline('simple', c.content, q, $5) AS snippet,
+                -ts_rank(c.search, q)::FLOAT8 AS rank
+            FROM clips c, plainto_tsquery('simple', $1) q
+            WHERE c.search @@ q
+                AND c.password IS NULL
+                AND NOT c.burn_after_read
+                AND c.max_views IS NULL
+                AND (c.expires_at IS NULL OR c.expires_at > $2)
+                AND (c.owner = $3 OR c.visibility = 'public')
+            ORDER BY rank
+            LIMIT $4"#,
+        )
+        .bind(&model.terms)
+        .bind(now())
+        .bind(&model.caller)
+        .bind(model.limit)
+        .bind(headline)
+        .fetch_all(&self.0)
+        .await?)
+    }
+
+    async fn delete_expired(&self) -> Result<u64> {
+        Ok(sqlx::query("DELETE FROM clips WHERE expires_at < $1")
+            .bind(now())
+            .execute(&self.0)
+            .await?
+            .rows_affected())
+    }
+
+    async fn delete_exhausted(&self) -> Result<u64> {
+        Ok(
+            sqlx::query("DELETE FROM clips WHERE max_views IS NOT NULL AND views >= max_views")
+                .execute(&self.0)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn stats(&self) -> Result<model::Stats> {
+        Ok(sqlx::query_as::<_, model::Stats>(
+            r#"SELECT
+                (SELECT COUNT(*) FROM clips) AS clips,
+                (SELECT COUNT(*) FROM clips WHERE expires_at < $1) AS expired_clips,
+                (SELECT COUNT(*) FROM clips WHERE attachment_filename IS NOT NULL) AS attachments,
+                (SELECT COALESCE(SUM(attachment_size), 0)::BIGINT FROM clips) AS attachment_bytes,
+                (SELECT COALESCE(SUM(views), 0)::BIGINT FROM clips) AS views,
+                (SELECT COUNT(*) FROM api_keys) AS api_keys"#,
+        )
+        .bind(now())
+        .fetch_one(&self.0)
+        .await?)
+    }
+
+    async fn put_attachment(&self, clip_id: &str, data: &[u8]) -> Result<()> {
+        sqlx::query("INSERT INTO attachment_blobs (clip_id, data) VALUES ($1, $2)")
+            .bind(clip_id)
+            .bind(data)
+            .execute(&self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_attachment(&self, clip_id: &str) -> Result<Vec<u8>> {
+        Ok(
+            sqlx::query_scalar("SELECT data FROM attachment_blobs WHERE clip_id = $1")
+                .bind(clip_id)
+                .fetch_one(&self.0)
+                .await?,
+        )
+    }
+
+    async fn delete_attachment(&self, clip_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM attachment_blobs WHERE clip_id = $1")
+            .bind(clip_id)
+            .execute(&self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn attachment_clip_ids(&self) -> Result<Vec<String>> {
+        Ok(
+            sqlx::query_scalar("SELECT id FROM clips WHERE attachment_filename IS NOT NULL")
+                .fetch_all(&self.0)
+                .await?,
+        )
+    }
+
+    async fn new_api_key(&self, model: model::NewApiKey) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO api_keys (api_key, prefix, key_hash, name, scopes, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+        )
+        .bind(&model.api_key)
+        .bind(&model.prefix)
+        .bind(&model.key_hash)
+        .bind(&model.name)
+        .bind(&model.scopes)
+        .bind(time(model.created_at))
+        .bind(model.expires_at.map(time))
+        .execute(&self.0)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_api_key(&self, prefix: &str) -> Result<model::ApiKey> {
+        Ok(sqlx::query_as::<_, model::ApiKey>(
+            "SELECT api_key, prefix, key_hash, scopes, expires_at FROM api_keys WHERE prefix = $1",
+        )
+        .bind(prefix)
+        .fetch_one(&self.0)
+        .await?)
+    }
+
+    async fn get_legacy_api_key(&self, legacy_key: &[u8]) -> Result<Option<model::ApiKey>> {
+        Ok(sqlx::query_as::<_, model::ApiKey>(
+            r#"SELECT api_key, prefix, key_hash, scopes, expires_at
+            FROM api_keys WHERE api_key = $1 AND key_hash IS NULL"#,
+        )
+        .bind(legacy_key)
+        .fetch_optional(&self.0)
+        .await?)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<model::ApiKeyDetails>> {
+        Ok(sqlx::query_as::<_, model::ApiKeyDetails>(
+            r#"SELECT prefix, name, scopes, created_at, expires_at, last_used_at
+            FROM api_keys ORDER BY created_at, prefix"#,
+        )
+        .fetch_all(&self.0)
+        .await?)
+    }
+
+    /// moves a key and its clips to a new id like `query::upgrade_api_key`
+    async fn upgrade_api_key(
+        &self,
+        legacy_key: &[u8],
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<Vec<u8>> {
+        let id = uuid::Uuid::new_v4().as_bytes().to_vec();
+        let mut transaction = self.0.begin().await?;
+
+        sqlx::query(
+            r#"INSERT INTO api_keys (api_key, prefix, key_hash, name, scopes, created_at,
+                expires_at, last_used_at)
+            SELECT $1, NULL, $2, name, scopes, created_at, expires_at, last_used_at
+            FROM api_keys WHERE api_key = $3 AND key_hash IS NULL"#,
+        )
+        .bind(&id)
+        .bind(key_hash)
+        .bind(legacy_key)
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query("UPDATE clips SET owner = $1 WHERE owner = $2")
+            .bind(&id)
+            .bind(legacy_key)
+            .execute(&mut *transaction)
+            .await?;
+        let moved: Option<String> =
+            sqlx::query_scalar("DELETE FROM api_keys WHERE api_key = $1 RETURNING prefix")
+                .bind(legacy_key)
+                .fetch_optional(&mut *transaction)
+                .await?;
+        if moved.is_none() {
+            // a request presenting the same key upgraded it first
+            transaction.rollback().await?;
+            return Ok(self.get_api_key(prefix).await?.api_key);
+        }
+        sqlx::query("UPDATE api_keys SET prefix = $1 WHERE api_key = $2")
+            .bind(prefix)
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(id)
+    }
+
+    async fn touch_api_key(&self, api_key: &[u8]) -> Result<()> {
+        let now = now();
+        sqlx::query(
+            r#"UPDATE api_keys SET last_used_at = $1
+            WHERE api_key = $2 AND (last_used_at IS NULL OR last_used_at < $3)"#,
+        )
+        .bind(now)
+        .bind(api_key)
+        .bind(now - chrono::Duration::minutes(1))
+        .execute(&self.0)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke_api_key(&self, prefix: &str) -> Result<RevocationStatus> {
+        Ok(sqlx::query("DELETE FROM api_keys WHERE prefix = $1")
+            .bind(prefix)
+            .execute(&self.0)
+            .await
+            .map(|result| match result.rows_affected() {
+                0 => RevocationStatus::NotFound,
+                _ => RevocationStatus::Revoked,
+            })?)
+    }
+
+    async fn migrate(&self) -> Result<Vec<i64>> {
+        let applied = applied_migrations(&self.0).await?;
+        MIGRATOR.run(&self.0).await?;
+        Ok(MIGRATOR
+            .iter()
+            .map(|migration| migration.version)
+            .filter(|version| !applied.contains(version))
+            .collect())
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>> {
+        Ok(applied_migrations(&self.0).await?.into_iter().max())
+    }
+}
+
+#[rocket::async_trait]
+impl ClipTransaction for sqlx::Transaction<'static, Postgres> {
+    async fn get_clip(&mut self, model: model::GetClip) -> Result<model::Clip> {
+        get_clip(model, &mut **self).await
+    }
+
+    async fn update_password(
+        &mut self,
+        short_code: &ShortCode,
+        password: Option<String>,
+    ) -> Result<()> {
+        update_password(short_code, password, &mut **self).await
+    }
+
+    async fn consume_view(&mut self, short_code: &ShortCode) -> Result<bool> {
+        consume_view(short_code, &mut **self).await
+    }
+
+    async fn delete_clip(&mut self, short_code: &ShortCode) -> Result<DeletionStatus> {
+        delete_clip(short_code, &mut **self).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        Ok((*self).commit().await?)
+    }
+}
@@ -1,13 +1,11 @@
 use super::model;
-use crate::{
-    data::{DataError, DatabasePool},
-    web::api::ApiKey,
-    ShortCode,
-};
+use crate::{data::DataError, domain::clip::field::ShortCodeGenerator, ShortCode};
+use chrono::Utc;
+use sqlx::{SqliteExecutor, SqlitePool};
 
 type Result<T> = std::result::Result<T, DataError>;
 
-pub async fn increase_views(short_code: &ShortCode, views: u32, pool: &DatabasePool) -> Result<()> {
+pub async fn increase_views(short_code: &ShortCode, views: u32, pool: &SqlitePool) -> Result<()> {
     let short_code = short_code.as_str();
     Ok(sqlx::query!(
         "UPDATE clips SET views = views + ? WHERE short_code = ?",
@@ -19,9 +17,9 @@ pub async fn increase_views(short_code: &ShortCode, views: u32, pool: &DatabaseP
     .map(|_| ())?)
 }
 
-pub async fn get_clip<M: Into<model::GetClip>>(
+pub async fn get_clip<'c, M: Into<model::GetClip>, E: SqliteExecutor<'c>>(
     model: M,
-    pool: &DatabasePool,
+    executor: E,
 ) -> Result<model::Clip> {
     let model = model.into();
     let short_code = model.short_code.as_str();
@@ -31,66 +29,483 @@ pub async fn get_clip<M: Into<model::GetClip>>(
         "SELECT * FROM clips WHERE short_code = ?",
         short_code
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?)
 }
 
+/// how often a generated short code is redrawn after colliding with an existing clip
+const SHORT_CODE_ATTEMPTS: usize = 10;
+
 pub async fn new_clip<M: Into<model::NewClip>>(
     model: M,
-    pool: &DatabasePool,
+    short_codes: &ShortCodeGenerator,
+    pool: &SqlitePool,
+) -> Result<model::Clip> {
+    insert_clip(model.into(), || short_codes.generate().into_inner(), pool).await
+}
+
+/// inserts a clip under its vanity code or the first code drawn from `next_code` that is free
+async fn insert_clip(
+    model: model::NewClip,
+    mut next_code: impl FnMut() -> String,
+    pool: &SqlitePool,
+) -> Result<model::Clip> {
+    for _ in 0..SHORT_CODE_ATTEMPTS {
+        let short_code = match &model.short_code {
+            Some(short_code) => short_code.clone(),
+            None => next_code(),
+        };
+
+        let inserted = sqlx::query!(
+            r#"INSERT INTO clips (
+                id, short_code, content, title, created_at, expires_at, password, views,
+                max_views, management_token, burn_after_read, owner, visibility,
+                attachment_filename, attachment_mime_type, attachment_size, language, rendering
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+            model.id,
+            short_code,
+            model.content,
+            model.title,
+            model.created_at,
+            model.expires_at,
+            model.password,
+            0,
+            model.max_views,
+            model.management_token,
+            model.burn_after_read,
+            model.owner,
+            model.visibility,
+            model.attachment_filename,
+            model.attachment_mime_type,
+            model.attachment_size,
+            model.language,
+            model.rendering
+        )
+        .execute(pool)
+        .await;
+
+        match inserted {
+            Ok(_) => return get_clip(short_code, pool).await,
+            Err(e) if is_short_code_collision(&e) => match model.short_code {
+                Some(short_code) => return Err(DataError::ShortCodeTaken(short_code)),
+                None => continue,
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(DataError::ShortCodeExhausted(SHORT_CODE_ATTEMPTS))
+}
+
+fn is_short_code_collision(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => {
+            e.is_unique_violation() && e.message().contains("clips.short_code")
+        }
+        _ => false,
+    }
+}
+
+/// counts a read of a clip with a view limit, reports false once the limit was reached
+pub async fn consume_view<'c, E: SqliteExecutor<'c>>(
+    short_code: &ShortCode,
+    executor: E,
+) -> Result<bool> {
+    let short_code = short_code.as_str();
+    Ok(sqlx::query!(
+        "UPDATE clips SET views = views + 1 WHERE short_code = ? AND views < max_views",
+        short_code
+    )
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected() > 0)?)
+}
+
+pub async fn update_clip<M: Into<model::UpdateClip>>(
+    model: M,
+    pool: &SqlitePool,
 ) -> Result<model::Clip> {
     let model = model.into();
+    let mut transaction = pool.begin().await?;
 
-    let _ = sqlx::query!(
-        r#"INSERT INTO clips (
-            id, short_code, content, title, created_at, expires_at, password, views
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
-        model.id,
-        model.short_code,
+    record_revision(&model.short_code, &mut *transaction).await?;
+    let updated_at = Utc::now().timestamp();
+    let result = sqlx::query!(
+        r#"UPDATE clips SET
+            revision = revision + 1,
+            updated_at = ?,
+            content = ?,
+            expires_at = ?,
+            password = ?,
+            title = ?,
+            language = ?,
+            rendering = ?
+        WHERE short_code = ?"#,
+        updated_at,
         model.content,
-        model.title,
-        model.created_at,
         model.expires_at,
         model.password,
-        0
+        model.title,
+        model.language,
+        model.rendering,
+        model.short_code,
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await?;
 
-    get_clip(model.short_code, pool).await
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound.into());
+    }
+
+    let clip = get_clip(model.short_code, &mut *transaction).await?;
+    transaction.commit().await?;
+    Ok(clip)
 }
 
-pub async fn update_clip<M: Into<model::UpdateClip>>(
+pub async fn patch_clip<M: Into<model::PatchClip>>(
     model: M,
-    pool: &DatabasePool,
+    pool: &SqlitePool,
 ) -> Result<model::Clip> {
     let model = model.into();
-    let _ = sqlx::query!(
+    let mut transaction = pool.begin().await?;
+
+    // a password change alone does not produce a new version of the clip
+    let revised = model.content.is_some() || model.set_title || model.set_expires_at;
+    if revised {
+        record_revision(&model.short_code, &mut *transaction).await?;
+    }
+    let updated_at = Utc::now().timestamp();
+    let result = sqlx::query!(
         r#"UPDATE clips SET
-            content = ?,
-            expires_at = ?,
-            password = ?,
-            title = ?
+            revision = revision + CASE WHEN ? THEN 1 ELSE 0 END,
+            updated_at = ?,
+            content = COALESCE(?, content),
+            title = CASE WHEN ? THEN ? ELSE title END,
+            expires_at = CASE WHEN ? THEN ? ELSE expires_at END,
+            password = CASE WHEN ? THEN ? ELSE password END,
+            visibility = COALESCE(?, visibility),
+            language = CASE WHEN ? THEN ? ELSE language END,
+            rendering = COALESCE(?, rendering)
         WHERE short_code = ?"#,
+        revised,
+        updated_at,
         model.content,
+        model.set_title,
+        model.title,
+        model.set_expires_at,
         model.expires_at,
+        model.set_password,
         model.password,
-        model.title,
+        model.visibility,
+        model.set_language,
+        model.language,
+        model.rendering,
         model.short_code,
     )
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound.into());
+    }
+
+    let clip = get_clip(model.short_code, &mut *transaction).await?;
+    transaction.commit().await?;
+    Ok(clip)
+}
+
+/// copies the current content, title and expiry of a clip into its history
+async fn record_revision<'c, E: SqliteExecutor<'c>>(short_code: &str, executor: E) -> Result<()> {
+    let revised_at = Utc::now().timestamp();
+    sqlx::query!(
+        r#"INSERT INTO clip_revisions (clip_id, revision, content, title, expires_at, revised_at)
+        SELECT
+            id,
+            COALESCE((SELECT MAX(revision) FROM clip_revisions WHERE clip_id = clips.id), 0) + 1,
+            content,
+            title,
+            expires_at,
+            ?
+        FROM clips WHERE short_code = ?"#,
+        revised_at,
+        short_code
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_revisions(
+    short_code: &ShortCode,
+    pool: &SqlitePool,
+) -> Result<Vec<model::Revision>> {
+    let short_code = short_code.as_str();
+    Ok(sqlx::query_as!(
+        model::Revision,
+        r#"SELECT r.revision, r.content, r.title, r.expires_at, r.revised_at
+        FROM clip_revisions r JOIN clips c ON c.id = r.clip_id
+        WHERE c.short_code = ?
+        ORDER BY r.revision"#,
+        short_code
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn get_revision(
+    short_code: &ShortCode,
+    revision: i64,
+    pool: &SqlitePool,
+) -> Result<model::Revision> {
+    let short_code = short_code.as_str();
+    Ok(sqlx::query_as!(
+        model::Revision,
+        r#"SELECT r.revision, r.content, r.title, r.expires_at, r.revised_at
+        FROM clip_revisions r JOIN clips c ON c.id = r.clip_id
+        WHERE c.short_code = ? AND r.revision = ?"#,
+        short_code,
+        revision
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn update_password<'c, E: SqliteExecutor<'c>>(
+    short_code: &ShortCode,
+    password: Option<String>,
+    executor: E,
+) -> Result<()> {
+    let short_code = short_code.as_str();
+    Ok(sqlx::query!(
+        "UPDATE clips SET password = ? WHERE short_code = ?",
+        password,
+        short_code
+    )
+    .execute(executor)
+    .await
+    .map(|_| ())?)
+}
+
+pub enum DeletionStatus {
+    Deleted,
+    NotFound,
+}
+
+pub async fn delete_clip<'c, E: SqliteExecutor<'c>>(
+    short_code: &ShortCode,
+    executor: E,
+) -> Result<DeletionStatus> {
+    let short_code = short_code.as_str();
+    Ok(
+        sqlx::query!("DELETE FROM clips WHERE short_code = ?", short_code)
+            .execute(executor)
+            .await
+            .map(|result| match result.rows_affected() {
+                0 => DeletionStatus::NotFound,
+                _ => DeletionStatus::Deleted,
+            })?,
+    )
+}
+
+/// one page of the clips created with an API key, fetching one extra row to tell whether
+/// another page follows
+pub async fn list_clips<M: Into<model::ListClips>>(
+    model: M,
+    pool: &SqlitePool,
+) -> Result<Vec<model::Clip>> {
+    use crate::service::ask::{ClipSort, ExpiryStatus, ListScope};
+
+    let model = model.into();
+    let column = match model.sort {
+        ClipSort::CreatedAt => "created_at",
+        ClipSort::Views => "views",
+    };
+    let scope = match model.scope {
+        ListScope::Mine => "owner = ?",
+        ListScope::Public => "visibility = 'public'",
+    };
+    let status = match model.status {
+        ExpiryStatus::All => "",
+        ExpiryStatus::Expired => "AND expires_at IS NOT NULL AND expires_at <= ?",
+        ExpiryStatus::Unexpired => "AND (expires_at IS NULL OR expires_at > ?)",
+    };
+    let after = match model.after {
+        Some(_) => format!("AND ({column} < ? OR ({column} = ? AND id < ?))"),
+        None => String::new(),
+    };
+    let sql = format!(
+        "SELECT * FROM clips WHERE {scope} {status} {after} \
+        ORDER BY {column} DESC, id DESC LIMIT ?"
+    );
+
+    let mut query = sqlx::query_as::<_, model::Clip>(&sql);
+    if model.scope == ListScope::Mine {
+        query = query.bind(model.owner);
+    }
+    if model.status != ExpiryStatus::All {
+        query = query.bind(Utc::now().timestamp());
+    }
+    if let Some((value, id)) = model.after {
+        query = query.bind(value).bind(value).bind(id);
+    }
+
+    Ok(query.bind(model.limit + 1).fetch_all(pool).await?)
+}
+
+/// ranked full-text matches among the caller's clips and everyone's public clips
+///
+/// Clips with a password are not indexed at all, clips that expire on reading are skipped as
+/// the snippet would bypass their view limit.
+pub async fn search_clips<M: Into<model::SearchClips>>(
+    model: M,
+    pool: &SqlitePool,
+) -> Result<Vec<model::SearchHit>> {
+    let model = model.into();
+    let now = Utc::now().timestamp();
+
+    Ok(sqlx::query_as!(
+        model::SearchHit,
+        r#"SELECT
+            c.short_code,
+            c.title,
+            c.created_at,
+            snippet(clips_search, 2, char(57344), char(57345), '…', 16) AS "snippet!: String",
+            bm25(clips_search, 0.0, 5.0, 1.0) AS "rank!: f64"
+        FROM clips_search s JOIN clips c ON c.id = s.clip_id
+        WHERE clips_search MATCH ?
+            AND c.password IS NULL
+            AND NOT c.burn_after_read
+            AND c.max_views IS NULL
+            AND (c.expires_at IS NULL OR c.expires_at > ?)
+            AND (c.owner = ? OR c.visibility = 'public')
+        ORDER BY bm25(clips_search, 0.0, 5.0, 1.0)
+        LIMIT ?"#,
+        model.query,
+        now,
+        model.caller,
+        model.limit
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn new_api_key<M: Into<model::NewApiKey>>(model: M, pool: &SqlitePool) -> Result<()> {
+    let model = model.into();
+    sqlx::query!(
+        r#"INSERT INTO api_keys (api_key, prefix, key_hash, name, scopes, created_at, expires_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+        model.api_key,
+        model.prefix,
+        model.key_hash,
+        model.name,
+        model.scopes,
+        model.created_at,
+        model.expires_at
+    )
     .execute(pool)
     .await?;
+    Ok(())
+}
 
-    get_clip(model.short_code, pool).await
+pub async fn get_api_key(prefix: &str, pool: &SqlitePool) -> Result<model::ApiKey> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"SELECT api_key AS "api_key!", prefix AS "prefix!", key_hash, scopes, expires_at
+        FROM api_keys WHERE prefix = ?"#,
+        prefix
+    )
+    .fetch_one(pool)
+    .await?)
 }
 
-pub async fn generate_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<ApiKey> {
-    let bytes = api_key.clone().into_inner();
-    sqlx::query!("INSERT INTO api_keys (api_key) VALUES (?)", bytes)
-        .execute(pool)
-        .await
-        .map(|_| ())?;
-    Ok(api_key)
+pub async fn get_legacy_api_key(
+    legacy_key: &[u8],
+    pool: &SqlitePool,
+) -> Result<Option<model::ApiKey>> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"SELECT api_key AS "api_key!", prefix AS "prefix!", key_hash, scopes, expires_at
+        FROM api_keys WHERE api_key = ? AND key_hash IS NULL"#,
+        legacy_key
+    )
+    .fetch_optional(pool)
+    .await?)
+}
+
+pub async fn list_api_keys(pool: &SqlitePool) -> Result<Vec<model::ApiKeyDetails>> {
+    Ok(sqlx::query_as!(
+        model::ApiKeyDetails,
+        r#"SELECT prefix AS "prefix!", name, scopes, created_at, expires_at, last_used_at
+        FROM api_keys ORDER BY created_at, prefix"#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// replaces a key issued before keys were hashed by its hash, returning the new id of the key
+///
+/// The raw key was also the id clips were owned by, so they are moved to the new id.
+pub async fn upgrade_api_key(
+    legacy_key: &[u8],
+    prefix: &str,
+    key_hash: &str,
+    pool: &SqlitePool,
+) -> Result<Vec<u8>> {
+    let id = uuid::Uuid::new_v4().as_bytes().to_vec();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        r#"INSERT INTO api_keys (api_key, prefix, key_hash, name, scopes, created_at, expires_at,
+            last_used_at)
+        SELECT ?, NULL, ?, name, scopes, created_at, expires_at, last_used_at
+        FROM api_keys WHERE api_key = ? AND key_hash IS NULL"#,
+        id,
+        key_hash,
+        legacy_key
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!("UPDATE clips SET owner = ? WHERE owner = ?", id, legacy_key)
+        .execute(&mut *transaction)
+        .await?;
+    // the new prefix is set once the legacy row is gone, a request upgrading the same key
+    // meanwhile finds nothing to delete
+    let moved = sqlx::query_scalar!(
+        r#"DELETE FROM api_keys WHERE api_key = ? RETURNING prefix AS "prefix!""#,
+        legacy_key
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if moved.is_none() {
+        // a request presenting the same key upgraded it first
+        transaction.rollback().await?;
+        return Ok(get_api_key(prefix, pool).await?.api_key);
+    }
+    sqlx::query!(
+        "UPDATE api_keys SET prefix = ? WHERE api_key = ?",
+        prefix,
+        id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(id)
+}
+
+/// records that a key was used, at most once a minute to spare the database a write per request
+pub async fn touch_api_key(api_key: &[u8], pool: &SqlitePool) -> Result<()> {
+    let now = Utc::now().timestamp();
+    sqlx::query!(
+        r#"UPDATE api_keys SET last_used_at = ?
+        WHERE api_key = ? AND (last_used_at IS NULL OR last_used_at < ? - 60)"#,
+        now,
+        api_key,
+        now
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
 }
 
 pub enum RevocationStatus {
@@ -98,10 +513,9 @@ pub enum RevocationStatus {
     NotFound,
 }
 
-pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<RevocationStatus> {
-    let bytes = api_key.clone().into_inner();
+pub async fn revoke_api_key(prefix: &str, pool: &SqlitePool) -> Result<RevocationStatus> {
     Ok(
-        sqlx::query!("DELETE FROM api_keys WHERE api_key == ?", bytes)
+        sqlx::query!("DELETE FROM api_keys WHERE prefix = ?", prefix)
             .execute(pool)
             .await
             .map(|result| match result.rows_affected() {
@@ -111,30 +525,76 @@ pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<Revo
     )
 }
 
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool> {
-    use sqlx::Row;
-    let bytes = api_key.clone().into_inner();
+pub async fn delete_expired(pool: &SqlitePool) -> Result<u64> {
     Ok(
-        sqlx::query("SELECT COUNT(api_key) FROM api_keys WHERE api_key = ?")
-            .bind(bytes)
-            .fetch_one(pool)
-            .await
-            .map(|row| {
-                let count: u32 = row.get(0);
-                count > 0
-            })?,
+        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires_at"#)
+            .execute(pool)
+            .await?
+            .rows_affected(),
     )
 }
 
-pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
+pub async fn delete_exhausted(pool: &SqlitePool) -> Result<u64> {
     Ok(
-        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires_at"#)
+        sqlx::query!(r#"DELETE FROM clips WHERE max_views IS NOT NULL AND views >= max_views"#)
             .execute(pool)
             .await?
             .rows_affected(),
     )
 }
 
+pub async fn put_attachment(clip_id: &str, data: &[u8], pool: &SqlitePool) -> Result<()> {
+    sqlx::query!(
+        "INSERT INTO attachment_blobs (clip_id, data) VALUES (?, ?)",
+        clip_id,
+        data
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_attachment(clip_id: &str, pool: &SqlitePool) -> Result<Vec<u8>> {
+    Ok(sqlx::query_scalar!(
+        "SELECT data FROM attachment_blobs WHERE clip_id = ?",
+        clip_id
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn delete_attachment(clip_id: &str, pool: &SqlitePool) -> Result<()> {
+    sqlx::query!("DELETE FROM attachment_blobs WHERE clip_id = ?", clip_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn attachment_clip_ids(pool: &SqlitePool) -> Result<Vec<String>> {
+    Ok(
+        sqlx::query_scalar!("SELECT id FROM clips WHERE attachment_filename IS NOT NULL")
+            .fetch_all(pool)
+            .await?,
+    )
+}
+
+pub async fn stats(pool: &SqlitePool) -> Result<model::Stats> {
+    Ok(sqlx::query_as!(
+        model::Stats,
+        r#"SELECT
+            (SELECT COUNT(*) FROM clips) AS "clips!: i64",
+            (SELECT COUNT(*) FROM clips WHERE strftime('%s', 'now') > expires_at)
+                AS "expired_clips!: i64",
+            (SELECT COUNT(*) FROM clips WHERE attachment_filename IS NOT NULL)
+                AS "attachments!: i64",
+            (SELECT COALESCE(SUM(attachment_size), 0) FROM clips) AS "attachment_bytes!: i64",
+            (SELECT COALESCE(SUM(views), 0) FROM clips) AS "views!: i64",
+            (SELECT COUNT(*) FROM api_keys) AS "api_keys!: i64""#
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::data::test::*;
@@ -153,21 +613,32 @@ pub mod test {
             id: DbId::new().into(),
             content: format!("content for clip '{}'", short_code),
             title: None,
-            short_code: short_code.into(),
+            short_code: Some(short_code.into()),
             created_at: Utc::now().timestamp(),
             expires_at: None,
             password: None,
+            max_views: None,
+            management_token: None,
+            burn_after_read: false,
+            owner: None,
+            visibility: "unlisted".to_owned(),
+            attachment_filename: None,
+            attachment_mime_type: None,
+            attachment_size: None,
+            language: None,
+            rendering: "code".to_owned(),
         }
     }
 
     #[test]
     fn clip_new_and_get() {
         let rt = async_runtime();
-        let db = new_db(rt.handle());
-        let pool = db.get_pool();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
 
-        let clip =
-            rt.block_on(async move { super::new_clip(model_new_clip("1"), &pool.clone()).await });
+        let clip = rt.block_on(async move {
+            super::new_clip(model_new_clip("1"), &Default::default(), &pool.clone()).await
+        });
 
         assert!(clip.is_ok());
 
@@ -176,4 +647,400 @@ pub mod test {
         assert!(clip.short_code == "1");
         assert!(clip.content == "content for clip '1'");
     }
+
+    #[test]
+    fn clip_password_is_stored_hashed() {
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
+        use crate::service;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+        let db: &DatabasePool = &store;
+
+        let req = service::ask::NewClip {
+            content: Content::new("content").unwrap(),
+            title: Title::default(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::new("123".to_owned()).unwrap(),
+            max_views: MaxViews::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+        };
+
+        let stored = rt.block_on(async move {
+            let (clip, _) =
+                service::action::new_clip(req, None, &Default::default(), &Default::default(), db)
+                    .await
+                    .unwrap();
+            super::get_clip(clip.short_code, pool).await.unwrap()
+        });
+
+        let password = stored.password.unwrap();
+        assert_ne!(password, "123");
+        assert!(password.starts_with("$argon2id$"));
+    }
+
+    #[test]
+    fn legacy_password_is_rehashed_on_access() {
+        use crate::domain::clip::field::Password;
+        use crate::service::{self, ask, ServiceError};
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+        let db: &DatabasePool = &store;
+
+        let mut clip = model_new_clip("legacy");
+        clip.password = Some("123".to_owned());
+
+        rt.block_on(async move {
+            super::new_clip(clip, &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let req = ask::GetClip {
+                short_code: "legacy".into(),
+                password: Password::new("abc".to_owned()).unwrap(),
+                caller: None,
+            };
+            let denied = service::action::get_clip(req, db).await;
+            assert!(matches!(denied, Err(ServiceError::PermissionError(_))));
+
+            let req = ask::GetClip {
+                short_code: "legacy".into(),
+                password: Password::new("123".to_owned()).unwrap(),
+                caller: None,
+            };
+            assert!(service::action::get_clip(req, db).await.is_ok());
+
+            let stored = super::get_clip(model_get_clip("legacy"), pool)
+                .await
+                .unwrap();
+            assert!(stored.password.unwrap().starts_with("$argon2id$"));
+
+            let req = ask::GetClip {
+                short_code: "legacy".into(),
+                password: Password::new("123".to_owned()).unwrap(),
+                caller: None,
+            };
+            assert!(service::action::get_clip(req, db).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn clip_update_keeps_short_code() {
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        rt.block_on(async move {
+            super::new_clip(model_new_clip("1"), &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let update = model::UpdateClip {
+                short_code: "1".into(),
+                content: "updated".into(),
+                title: Some("title".into()),
+                expires_at: None,
+                password: None,
+                language: None,
+                rendering: "code".to_owned(),
+            };
+            let clip = super::update_clip(update, pool).await.unwrap();
+            assert_eq!(clip.short_code, "1");
+            assert_eq!(clip.content, "updated");
+            assert_eq!(clip.title.as_deref(), Some("title"));
+        });
+    }
+
+    #[test]
+    fn clip_patch_keeps_and_clears_fields() {
+        use crate::service::ask::{Patch, PatchClip};
+        use crate::ShortCode;
+        use std::convert::TryFrom;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        rt.block_on(async move {
+            let mut clip = model_new_clip("1");
+            clip.title = Some("title".into());
+            clip.expires_at = Some(32503680000);
+            super::new_clip(clip, &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let req: PatchClip =
+                serde_json::from_str(r#"{"content": "updated", "title": null}"#).unwrap();
+            assert!(matches!(req.title, Patch::Clear));
+            assert!(req.expires_at.is_keep());
+
+            let patch = model::PatchClip::try_from((ShortCode::from("1"), req)).unwrap();
+            let clip = super::patch_clip(patch, pool).await.unwrap();
+            assert_eq!(clip.content, "updated");
+            assert_eq!(clip.title, None);
+            assert!(clip.expires_at.is_some());
+        });
+    }
+
+    #[test]
+    fn clip_update_missing_is_not_found() {
+        use crate::service::ask::PatchClip;
+        use crate::ShortCode;
+        use std::convert::TryFrom;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        rt.block_on(async move {
+            let patch =
+                model::PatchClip::try_from((ShortCode::from("missing"), PatchClip::default()))
+                    .unwrap();
+            let result = super::patch_clip(patch, pool).await;
+            assert!(matches!(
+                result,
+                Err(DataError::Database(sqlx::Error::RowNotFound))
+            ));
+        });
+    }
+
+    #[test]
+    fn clip_delete() {
+        use crate::ShortCode;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        rt.block_on(async move {
+            super::new_clip(model_new_clip("1"), &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let short_code = ShortCode::from("1");
+            let status = super::delete_clip(&short_code, pool).await.unwrap();
+            assert!(matches!(status, super::DeletionStatus::Deleted));
+            assert!(super::get_clip(model_get_clip("1"), pool).await.is_err());
+
+            let status = super::delete_clip(&short_code, pool).await.unwrap();
+            assert!(matches!(status, super::DeletionStatus::NotFound));
+        });
+    }
+
+    #[test]
+    fn clip_view_limit() {
+        use crate::ShortCode;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        rt.block_on(async move {
+            let mut clip = model_new_clip("1");
+            clip.max_views = Some(2);
+            super::new_clip(clip, &Default::default(), pool)
+                .await
+                .unwrap();
+            super::new_clip(model_new_clip("2"), &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let short_code = ShortCode::from("1");
+            assert!(super::consume_view(&short_code, pool).await.unwrap());
+            assert!(super::consume_view(&short_code, pool).await.unwrap());
+            assert!(!super::consume_view(&short_code, pool).await.unwrap());
+
+            assert_eq!(super::delete_exhausted(pool).await.unwrap(), 1);
+            assert!(super::get_clip(model_get_clip("1"), pool).await.is_err());
+            assert!(super::get_clip(model_get_clip("2"), pool).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn clip_short_code_retries_on_collision() {
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        // codes are drawn from a script so the collisions do not depend on the generator's luck
+        let (first, second, third) = rt.block_on(async move {
+            let generated = || {
+                let mut clip = model_new_clip("unused");
+                clip.short_code = None;
+                clip
+            };
+            let scripted = |codes: &'static [&'static str]| {
+                let mut codes = codes.iter().cycle();
+                move || codes.next().unwrap().to_string()
+            };
+            let first = super::insert_clip(generated(), scripted(&["a"]), pool)
+                .await
+                .unwrap();
+            let second = super::insert_clip(generated(), scripted(&["a", "a", "b"]), pool)
+                .await
+                .unwrap();
+            let third = super::insert_clip(generated(), scripted(&["a", "b"]), pool).await;
+            (first, second, third)
+        });
+
+        assert_eq!(first.short_code, "a");
+        assert_eq!(second.short_code, "b");
+        assert!(matches!(third, Err(DataError::ShortCodeExhausted(_))));
+    }
+
+    #[test]
+    fn clip_revisions_record_prior_versions() {
+        use crate::service::ask::PatchClip;
+        use crate::ShortCode;
+        use std::convert::TryFrom;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+
+        rt.block_on(async move {
+            let short_code = ShortCode::from("1");
+            super::new_clip(model_new_clip("1"), &Default::default(), pool)
+                .await
+                .unwrap();
+
+            for (content, title) in [("second", "two"), ("third", "three")] {
+                let req: PatchClip = serde_json::from_value(
+                    serde_json::json!({ "content": content, "title": title }),
+                )
+                .unwrap();
+                let patch = model::PatchClip::try_from((short_code.clone(), req)).unwrap();
+                super::patch_clip(patch, pool).await.unwrap();
+            }
+
+            // changing only the password keeps the history as it is
+            let req: PatchClip = serde_json::from_str(r#"{"password": "secret"}"#).unwrap();
+            let patch = model::PatchClip::try_from((short_code.clone(), req)).unwrap();
+            super::patch_clip(patch, pool).await.unwrap();
+
+            let revisions = super::get_revisions(&short_code, pool).await.unwrap();
+            assert_eq!(revisions.len(), 2);
+            assert_eq!(revisions[0].revision, 1);
+            assert_eq!(revisions[0].content, "content for clip '1'");
+            assert_eq!(revisions[0].title, None);
+            assert_eq!(revisions[1].content, "second");
+            assert_eq!(revisions[1].title.as_deref(), Some("two"));
+
+            let revision = super::get_revision(&short_code, 2, pool).await.unwrap();
+            assert_eq!(revision.content, "second");
+            assert!(super::get_revision(&short_code, 3, pool).await.is_err());
+
+            super::delete_clip(&short_code, pool).await.unwrap();
+            let revisions = super::get_revisions(&short_code, pool).await.unwrap();
+            assert!(revisions.is_empty());
+            let orphans = sqlx::query!("SELECT COUNT(*) AS count FROM clip_revisions")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            assert_eq!(orphans.count, 0);
+        });
+    }
+
+    #[test]
+    fn legacy_api_keys_are_hashed_on_first_use() {
+        use crate::service::action;
+        use crate::web::api::RawApiKey;
+        use base64::{engine::general_purpose, Engine as _};
+        use std::str::FromStr;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+        let db: &DatabasePool = &store;
+
+        rt.block_on(async move {
+            // keys issued before hashing were stored as they were handed out
+            let legacy = vec![7u8; 16];
+            sqlx::query!(
+                "INSERT INTO api_keys (api_key, prefix, scopes) VALUES (?, 'a1b2c3d4e5f6', 'clip:read')",
+                legacy
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+            let mut clip = model_new_clip("1");
+            clip.owner = Some(legacy.clone());
+            super::new_clip(clip, &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let raw_key = RawApiKey::from_str(&general_purpose::STANDARD.encode(&legacy)).unwrap();
+            let api_key = action::authenticate_api_key(&raw_key, db).await.unwrap();
+            // the placeholder prefix is replaced by one that gives nothing of the key away
+            assert_eq!(api_key.prefix(), raw_key.prefix());
+            assert!(super::get_api_key("a1b2c3d4e5f6", pool).await.is_err());
+
+            let stored = super::get_api_key(&raw_key.prefix(), pool).await.unwrap();
+            assert_ne!(stored.api_key, legacy);
+            assert_eq!(stored.key_hash, Some(raw_key.hash()));
+
+            let owner = sqlx::query_scalar!("SELECT owner FROM clips WHERE short_code = '1'")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            assert_eq!(owner, Some(stored.api_key.clone()));
+
+            let again = action::authenticate_api_key(&raw_key, db).await.unwrap();
+            assert_eq!(again.into_inner(), stored.api_key.clone());
+
+            // a concurrent request that read the key before it was upgraded
+            let upgraded = super::upgrade_api_key(&legacy, &raw_key.prefix(), &raw_key.hash(), pool)
+                .await
+                .unwrap();
+            assert_eq!(upgraded, stored.api_key);
+        });
+    }
+
+    #[test]
+    fn stats_and_key_listing() {
+        use crate::service::{action, ask};
+        use crate::web::api::Scope;
+
+        let rt = async_runtime();
+        let store = new_store(rt.handle());
+        let pool = store.pool();
+        let db: &DatabasePool = &store;
+
+        rt.block_on(async move {
+            super::new_clip(model_new_clip("1"), &Default::default(), pool)
+                .await
+                .unwrap();
+            let mut expired = model_new_clip("2");
+            expired.expires_at = Some(0);
+            super::new_clip(expired, &Default::default(), pool)
+                .await
+                .unwrap();
+            let req = ask::NewApiKey {
+                name: "ops".to_owned(),
+                scopes: vec![Scope::Admin],
+                expires_at: Default::default(),
+            };
+            let raw_key = action::generate_api_key(req, db).await.unwrap();
+
+            let stats = action::stats(db).await.unwrap();
+            assert_eq!(stats.clips, 2);
+            assert_eq!(stats.expired_clips, 1);
+            assert_eq!(stats.attachments, 0);
+            assert_eq!(stats.api_keys, 1);
+
+            let keys = action::list_api_keys(db).await.unwrap();
+            assert_eq!(keys.len(), 1);
+            assert_eq!(keys[0].prefix, raw_key.prefix());
+            assert_eq!(keys[0].name, "ops");
+            assert_eq!(keys[0].scopes, vec![Scope::Admin]);
+            assert!(keys[0].last_used_at.is_none());
+        });
+    }
 }
@@ -0,0 +1,211 @@
+use super::model;
+use super::query::{self, DeletionStatus, RevocationStatus};
+use super::store::{ClipStore, ClipTransaction};
+use super::DataError;
+use crate::domain::clip::field::ShortCodeGenerator;
+use crate::ShortCode;
+use sqlx::{Sqlite, SqlitePool};
+
+type Result<T> = std::result::Result<T, DataError>;
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
+
+/// Clips in a SQLite database, the queries live in `data::query`.
+#[derive(Debug, Clone)]
+pub struct SqliteStore(SqlitePool);
+
+impl SqliteStore {
+    /// connects to the database, creating the file when it does not exist yet
+    pub async fn connect(connection_str: &str) -> Result<Self> {
+        use std::str::FromStr;
+
+        let options =
+            sqlx::sqlite::SqliteConnectOptions::from_str(connection_str)?.create_if_missing(true);
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .connect_with(options)
+            .await?;
+        Ok(Self(pool))
+    }
+
+    pub fn new(pool: SqlitePool) -> Self {
+        Self(pool)
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl ClipStore for SqliteStore {
+    async fn begin(&self) -> Result<Box<dyn ClipTransaction>> {
+        Ok(Box::new(self.0.begin().await?))
+    }
+
+    async fn increase_views(&self, short_code: &ShortCode, views: u32) -> Result<()> {
+        query::increase_views(short_code, views, &self.0).await
+    }
+
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip> {
+        query::get_clip(model, &self.0).await
+    }
+
+    async fn new_clip(
+        &self,
+        model: model::NewClip,
+        short_codes: &ShortCodeGenerator,
+    ) -> Result<model::Clip> {
+        query::new_clip(model, short_codes, &self.0).await
+    }
+
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip> {
+        query::update_clip(model, &self.0).await
+    }
+
+    async fn patch_clip(&self, model: model::PatchClip) -> Result<model::Clip> {
+        query::patch_clip(model, &self.0).await
+    }
+
+    async fn delete_clip(&self, short_code: &ShortCode) -> Result<DeletionStatus> {
+        query::delete_clip(short_code, &self.0).await
+    }
+
+    async fn get_revisions(&self, short_code: &ShortCode) -> Result<Vec<model::Revision>> {
+        query::get_revisions(short_code, &self.0).await
+    }
+
+    async fn get_revision(&self, short_code: &ShortCode, revision: i64) -> Result<model::Revision> {
+        query::get_revision(short_code, revision, &self.0).await
+    }
+
+    async fn list_clips(&self, model: model::ListClips) -> Result<Vec<model::Clip>> {
+        query::list_clips(model, &self.0).await
+    }
+
+    async fn search_clips(&self, model: model::SearchClips) -> Result<Vec<model::SearchHit>> {
+        query::search_clips(model, &self.0).await
+    }
+
+    async fn delete_expired(&self) -> Result<u64> {
+        query::delete_expired(&self.0).await
+    }
+
+    async fn delete_exhausted(&self) -> Result<u64> {
+        query::delete_exhausted(&self.0).await
+    }
+
+    async fn stats(&self) -> Result<model::Stats> {
+        query::stats(&self.0).await
+    }
+
+    async fn put_attachment(&self, clip_id: &str, data: &[u8]) -> Result<()> {
+        query::put_attachment(clip_id, data, &self.0).await
+    }
+
+    async fn get_attachment(&self, clip_id: &str) -> Result<Vec<u8>> {
+        query::get_attachment(clip_id, &self.0).await
+    }
+
+    async fn delete_attachment(&self, clip_id: &str) -> Result<()> {
+        query::delete_attachment(clip_id, &self.0).await
+    }
+
+    async fn attachment_clip_ids(&self) -> Result<Vec<String>> {
+        query::attachment_clip_ids(&self.0).await
+    }
+
+    async fn new_api_key(&self, model: model::NewApiKey) -> Result<()> {
+        query::new_api_key(model, &self.0).await
+    }
+
+    async fn get_api_key(&self, prefix: &str) -> Result<model::ApiKey> {
+        query::get_api_key(prefix, &self.0).await
+    }
+
+    async fn get_legacy_api_key(&self, legacy_key: &[u8]) -> Result<Option<model::ApiKey>> {
+        query::get_legacy_api_key(legacy_key, &self.0).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<model::ApiKeyDetails>> {
+        query::list_api_keys(&self.0).await
+    }
+
+    async fn upgrade_api_key(
+        &self,
+        legacy_key: &[u8],
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<Vec<u8>> {
+        query::upgrade_api_key(legacy_key, prefix, key_hash, &self.0).await
+    }
+
+    async fn touch_api_key(&self, api_key: &[u8]) -> Result<()> {
+        query::touch_api_key(api_key, &self.0).await
+    }
+
+    async fn revoke_api_key(&self, prefix: &str) -> Result<RevocationStatus> {
+        query::revoke_api_key(prefix, &self.0).await
+    }
+
+    async fn migrate(&self) -> Result<Vec<i64>> {
+        let applied = applied_migrations(&self.0).await?;
+        MIGRATOR.run(&self.0).await?;
+        Ok(MIGRATOR
+            .iter()
+            .map(|migration| migration.version)
+            .filter(|version| !applied.contains(version))
+            .collect())
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>> {
+        Ok(applied_migrations(&self.0).await?.into_iter().max())
+    }
+}
+
+async fn applied_migrations(pool: &SqlitePool) -> Result<Vec<i64>> {
+    use sqlx::migrate::Migrate;
+
+    let mut conn = pool.acquire().await?;
+    // only `migrate` may create the table, a database opened without migrating stays untouched
+    let exists: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+#[rocket::async_trait]
+impl ClipTransaction for sqlx::Transaction<'static, Sqlite> {
+    async fn get_clip(&mut self, model: model::GetClip) -> Result<model::Clip> {
+        query::get_clip(model, &mut **self).await
+    }
+
+    async fn update_password(
+        &mut self,
+        short_code: &ShortCode,
+        password: Option<String>,
+    ) -> Result<()> {
+        query::update_password(short_code, password, &mut **self).await
+    }
+
+    async fn consume_view(&mut self, short_code: &ShortCode) -> Result<bool> {
+        query::consume_view(short_code, &mut **self).await
+    }
+
+    async fn delete_clip(&mut self, short_code: &ShortCode) -> Result<DeletionStatus> {
+        query::delete_clip(short_code, &mut **self).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        Ok((*self).commit().await?)
+    }
+}
@@ -0,0 +1,76 @@
+use super::model;
+use super::query::{DeletionStatus, RevocationStatus};
+use super::DataError;
+use crate::domain::clip::field::ShortCodeGenerator;
+use crate::ShortCode;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+/// The operations the service layer needs from a database.
+///
+/// `SqliteStore` and `PostgresStore` implement it, `Database::new` picks one from the
+/// connection string.
+#[rocket::async_trait]
+pub trait ClipStore: Send + Sync {
+    /// starts a transaction for reading a clip, dropping it without `commit` rolls it back
+    async fn begin(&self) -> Result<Box<dyn ClipTransaction>>;
+
+    async fn increase_views(&self, short_code: &ShortCode, views: u32) -> Result<()>;
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip>;
+    async fn new_clip(
+        &self,
+        model: model::NewClip,
+        short_codes: &ShortCodeGenerator,
+    ) -> Result<model::Clip>;
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip>;
+    async fn patch_clip(&self, model: model::PatchClip) -> Result<model::Clip>;
+    async fn delete_clip(&self, short_code: &ShortCode) -> Result<DeletionStatus>;
+    async fn get_revisions(&self, short_code: &ShortCode) -> Result<Vec<model::Revision>>;
+    async fn get_revision(&self, short_code: &ShortCode, revision: i64) -> Result<model::Revision>;
+    async fn list_clips(&self, model: model::ListClips) -> Result<Vec<model::Clip>>;
+    async fn search_clips(&self, model: model::SearchClips) -> Result<Vec<model::SearchHit>>;
+    async fn delete_expired(&self) -> Result<u64>;
+    async fn delete_exhausted(&self) -> Result<u64>;
+    async fn stats(&self) -> Result<model::Stats>;
+
+    async fn put_attachment(&self, clip_id: &str, data: &[u8]) -> Result<()>;
+    async fn get_attachment(&self, clip_id: &str) -> Result<Vec<u8>>;
+    async fn delete_attachment(&self, clip_id: &str) -> Result<()>;
+    /// ids of the clips that have an attachment, wherever it is stored
+    async fn attachment_clip_ids(&self) -> Result<Vec<String>>;
+
+    async fn new_api_key(&self, model: model::NewApiKey) -> Result<()>;
+    async fn get_api_key(&self, prefix: &str) -> Result<model::ApiKey>;
+    /// a key issued before hashing that was not upgraded yet, found by the key itself
+    async fn get_legacy_api_key(&self, legacy_key: &[u8]) -> Result<Option<model::ApiKey>>;
+    async fn list_api_keys(&self) -> Result<Vec<model::ApiKeyDetails>>;
+    /// moves a key issued before hashing to a new id and prefix, returning the id it ended up with
+    async fn upgrade_api_key(
+        &self,
+        legacy_key: &[u8],
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<Vec<u8>>;
+    async fn touch_api_key(&self, api_key: &[u8]) -> Result<()>;
+    async fn revoke_api_key(&self, prefix: &str) -> Result<RevocationStatus>;
+
+    /// applies pending migrations, returning their versions
+    async fn migrate(&self) -> Result<Vec<i64>>;
+    /// the newest migration applied to the database, if any
+    async fn schema_version(&self) -> Result<Option<i64>>;
+}
+
+/// The steps of reading a clip that have to succeed or fail together.
+#[rocket::async_trait]
+pub trait ClipTransaction: Send {
+    async fn get_clip(&mut self, model: model::GetClip) -> Result<model::Clip>;
+    async fn update_password(
+        &mut self,
+        short_code: &ShortCode,
+        password: Option<String>,
+    ) -> Result<()>;
+    /// counts a read of a clip with a view limit, reports false once the limit was reached
+    async fn consume_view(&mut self, short_code: &ShortCode) -> Result<bool>;
+    async fn delete_clip(&mut self, short_code: &ShortCode) -> Result<DeletionStatus>;
+    async fn commit(self: Box<Self>) -> Result<()>;
+}
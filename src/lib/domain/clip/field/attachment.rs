@@ -0,0 +1,98 @@
+use crate::domain::clip::ClipError;
+use rocket::http::ContentType;
+use serde::{Deserialize, Serialize};
+
+const MAX_FILENAME_LENGTH: usize = 255;
+const FALLBACK_FILENAME: &str = "attachment";
+
+/// A file carried by a clip in place of text, the bytes themselves live in a `BlobStore`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Attachment {
+    filename: String,
+    mime_type: String,
+    size: u64,
+}
+
+impl Attachment {
+    /// strips directories and control characters from the filename and guesses the MIME type
+    /// from its extension when the client did not send a specific one
+    pub fn new(filename: &str, mime_type: Option<&str>, size: u64) -> Result<Self, ClipError> {
+        if size == 0 {
+            return Err(ClipError::InvalidAttachment("the file is empty".to_owned()));
+        }
+
+        let filename = sanitize_filename(filename);
+        let mime_type = mime_type
+            .and_then(ContentType::parse_flexible)
+            .filter(|content_type| *content_type != ContentType::Binary)
+            .or_else(|| {
+                filename
+                    .rsplit_once('.')
+                    .and_then(|(_, extension)| ContentType::from_extension(extension))
+            })
+            .unwrap_or(ContentType::Binary);
+
+        Ok(Self {
+            filename,
+            mime_type: mime_type.to_string(),
+            size,
+        })
+    }
+
+    pub fn filename(&self) -> &str {
+        self.filename.as_str()
+    }
+
+    pub fn mime_type(&self) -> &str {
+        self.mime_type.as_str()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+fn sanitize_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_LENGTH)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => FALLBACK_FILENAME.to_owned(),
+        name => name.to_owned(),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Attachment;
+
+    #[test]
+    fn sanitizes_filename() {
+        let attachment = Attachment::new("../../etc/passwd", None, 1).unwrap();
+        assert_eq!(attachment.filename(), "passwd");
+        let attachment = Attachment::new("C:\\Users\\me\\shot\".png", None, 1).unwrap();
+        assert_eq!(attachment.filename(), "shot.png");
+        let attachment = Attachment::new("..", None, 1).unwrap();
+        assert_eq!(attachment.filename(), "attachment");
+    }
+
+    #[test]
+    fn guesses_mime_type() {
+        let attachment = Attachment::new("report.pdf", None, 1).unwrap();
+        assert_eq!(attachment.mime_type(), "application/pdf");
+        let attachment = Attachment::new("shot.png", Some("application/octet-stream"), 1).unwrap();
+        assert_eq!(attachment.mime_type(), "image/png");
+        let attachment = Attachment::new("logs", Some("application/gzip"), 1).unwrap();
+        assert_eq!(attachment.mime_type(), "application/gzip");
+        let attachment = Attachment::new("data.unknown", None, 1).unwrap();
+        assert_eq!(attachment.mime_type(), "application/octet-stream");
+    }
+
+    #[test]
+    fn rejects_empty_file() {
+        assert!(Attachment::new("empty.txt", None, 0).is_err());
+    }
+}
@@ -1,9 +1,9 @@
-use crate::domain::{clip::ClipError, time::Time};
+use crate::domain::{clip::ClipError, time, time::Time};
 use rocket::form::{self, FromFormField, ValueField};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::str::FromStr;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct ExpiresAt(Option<Time>);
 
 impl ExpiresAt {
@@ -22,16 +22,37 @@ impl Default for ExpiresAt {
     }
 }
 
+/// Accepts a relative duration (`10m`, `1h`, `7d`), an RFC 3339 timestamp or a `YYYY-MM-DD`
+/// date. Expiry dates in the past are rejected.
 impl FromStr for ExpiresAt {
     type Err = ClipError;
     fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
         if raw.is_empty() {
-            Ok(Self(None))
+            return Ok(Self(None));
+        }
+
+        let time = match time::parse_duration(raw) {
+            Ok(duration) => Time::from_now(duration)
+                .ok_or_else(|| ClipError::InvalidDate("expiry date is too far away".to_owned()))?,
+            Err(_) => Time::from_str(raw)?,
+        };
+
+        if time.is_past() {
+            Err(ClipError::InvalidDate(
+                "expiry date must be in the future".to_owned(),
+            ))
         } else {
-            match Time::from_str(raw) {
-                Ok(time) => Ok(Self::new(time)),
-                Err(e) => Err(e.into()),
-            }
+            Ok(Self::new(time))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpiresAt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(raw) => Self::from_str(raw.as_str()).map_err(serde::de::Error::custom),
+            None => Ok(Self(None)),
         }
     }
 }
@@ -46,4 +67,52 @@ impl<'r> FromFormField<'r> for ExpiresAt {
                 .map_err(|e| form::Error::validation(format!("{}", e)))?)
         }
     }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ExpiresAt;
+    use chrono::{Duration, Utc};
+    use std::str::FromStr;
+
+    fn parse(raw: &str) -> Option<chrono::DateTime<Utc>> {
+        ExpiresAt::from_str(raw)
+            .unwrap()
+            .into_inner()
+            .map(|time| time.into_inner())
+    }
+
+    #[test]
+    fn parses_relative_durations() {
+        let expires_at = parse("10m").unwrap();
+        assert!(expires_at > Utc::now() + Duration::minutes(9));
+        assert!(expires_at <= Utc::now() + Duration::minutes(10));
+
+        let expires_at = parse("1h30m").unwrap();
+        assert!(expires_at > Utc::now() + Duration::minutes(89));
+
+        assert!(ExpiresAt::from_str("10x").is_err());
+        assert!(ExpiresAt::from_str("0d").is_err());
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        let expires_at = parse("2999-01-01T12:30:00+02:00").unwrap();
+        assert_eq!(expires_at.to_rfc3339(), "2999-01-01T10:30:00+00:00");
+
+        let expires_at = parse("2999-01-01").unwrap();
+        assert_eq!(expires_at.to_rfc3339(), "2999-01-01T00:00:00+00:00");
+
+        assert!(parse("").is_none());
+    }
+
+    #[test]
+    fn rejects_past_dates() {
+        assert!(ExpiresAt::from_str("2000-01-01").is_err());
+        assert!(ExpiresAt::from_str("2000-01-01T00:00:00Z").is_err());
+    }
 }
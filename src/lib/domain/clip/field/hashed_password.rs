@@ -0,0 +1,46 @@
+use crate::domain::clip::field::Password;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use subtle::ConstantTimeEq;
+
+/// Password as it is stored in the database.
+///
+/// Holds an Argon2id PHC string. Rows written before hashing was introduced
+/// still hold the raw password; those are reported by `is_legacy`.
+#[derive(Clone, Debug, Default)]
+pub struct HashedPassword(Option<String>);
+
+impl HashedPassword {
+    pub fn new<T: Into<Option<String>>>(hash: T) -> Self {
+        Self(hash.into().filter(|hash| !hash.is_empty()))
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// true when the stored value is a plaintext password that should be rehashed
+    pub fn is_legacy(&self) -> bool {
+        match &self.0 {
+            Some(stored) => PasswordHash::new(stored).is_err(),
+            None => false,
+        }
+    }
+
+    /// checks the password in constant time, returns false when there is nothing to check against
+    pub fn verify(&self, password: &Password) -> bool {
+        let (Some(stored), Some(password)) = (self.0.as_deref(), password.as_str()) else {
+            return false;
+        };
+
+        match PasswordHash::new(stored) {
+            Ok(hash) => Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok(),
+            Err(_) => stored.as_bytes().ct_eq(password.as_bytes()).into(),
+        }
+    }
+}
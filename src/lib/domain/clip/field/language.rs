@@ -0,0 +1,319 @@
+use crate::domain::clip::ClipError;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::str::FromStr;
+
+/// Languages clips can be highlighted as, along with the other names they are known by.
+pub const LANGUAGES: &[(&str, &[&str])] = &[
+    ("text", &["plain", "plaintext", "txt"]),
+    ("bash", &["sh", "shell", "zsh"]),
+    ("c", &["h"]),
+    ("cpp", &["c++", "cc", "cxx", "hpp"]),
+    ("css", &[]),
+    ("go", &["golang"]),
+    ("html", &["htm"]),
+    ("java", &[]),
+    ("javascript", &["js", "jsx", "mjs"]),
+    ("json", &[]),
+    ("markdown", &["md"]),
+    ("php", &[]),
+    ("python", &["py"]),
+    ("ruby", &["rb"]),
+    ("rust", &["rs"]),
+    ("sql", &[]),
+    ("xml", &[]),
+    ("yaml", &["yml"]),
+];
+
+/// Hints for `Language::detect`, a language needs at least two of them to be picked.
+const HINTS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ",
+            "let mut ",
+            "impl ",
+            "pub fn ",
+            "use std::",
+            "#[derive(",
+            "&self",
+            "-> ",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "import ", "self.", "elif ", "print(", "__init__", "None", "True:",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "require(",
+            "document.",
+            "export ",
+        ],
+    ),
+    ("go", &["package ", "func ", ":= ", "fmt.", "import ("]),
+    (
+        "c",
+        &[
+            "#include <stdio.h>",
+            "#include",
+            "printf(",
+            "int main(",
+            "malloc(",
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            "#include <iostream>",
+            "std::",
+            "cout",
+            "template<",
+            "namespace ",
+        ],
+    ),
+    (
+        "java",
+        &[
+            "public class ",
+            "public static void main",
+            "System.out",
+            "import java.",
+            "@Override",
+        ],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            " WHERE ",
+            " FROM ",
+            " JOIN ",
+        ],
+    ),
+    (
+        "css",
+        &["color:", "margin:", "padding:", "font-", "px;", "display:"],
+    ),
+    ("bash", &["echo ", "fi\n", "then\n", "$(", "done\n", "esac"]),
+    (
+        "ruby",
+        &["puts ", "end\n", "require '", "attr_accessor", ".each do"],
+    ),
+    ("markdown", &["# ", "## ", "```", "](", "**"]),
+];
+
+/// The syntax a clip is highlighted as, missing when it could not be detected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Language(Option<String>);
+
+impl Language {
+    /// accepts the names in `LANGUAGES` in any case
+    pub fn new(language: &str) -> Result<Self, ClipError> {
+        let lowercase = language.trim().to_ascii_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(name, aliases)| *name == lowercase || aliases.contains(&lowercase.as_str()))
+            .map(|(name, _)| Self(Some((*name).to_owned())))
+            .ok_or_else(|| ClipError::InvalidLanguage(format!("unknown language '{}'", language)))
+    }
+
+    /// guesses the language of some code from its shebang, its first line or common keywords
+    pub fn detect(content: &str) -> Self {
+        let detected = detect_by_first_line(content)
+            .or_else(|| is_json(content).then_some("json"))
+            .or_else(|| detect_by_hints(content));
+        Self(detected.map(str::to_owned))
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// the type the raw content is served as
+    ///
+    /// Markup that a browser would render, like HTML or XML, is served as plain text so clips
+    /// never run scripts on this origin.
+    pub fn mime_type(&self) -> &'static str {
+        match self.as_deref() {
+            Some("css") => "text/css",
+            Some("javascript") => "text/javascript",
+            Some("json") => "application/json",
+            Some("markdown") => "text/markdown",
+            Some("yaml") => "application/yaml",
+            _ => "text/plain",
+        }
+    }
+
+    /// the extension of files written in the language
+    pub fn extension(&self) -> &'static str {
+        match self.as_deref() {
+            Some("bash") => "sh",
+            Some("c") => "c",
+            Some("cpp") => "cpp",
+            Some("css") => "css",
+            Some("go") => "go",
+            Some("html") => "html",
+            Some("java") => "java",
+            Some("javascript") => "js",
+            Some("json") => "json",
+            Some("markdown") => "md",
+            Some("php") => "php",
+            Some("python") => "py",
+            Some("ruby") => "rb",
+            Some("rust") => "rs",
+            Some("sql") => "sql",
+            Some("xml") => "xml",
+            Some("yaml") => "yaml",
+            _ => "txt",
+        }
+    }
+}
+
+fn detect_by_first_line(content: &str) -> Option<&'static str> {
+    let trimmed = content.trim_start();
+    let first_line = trimmed.lines().next().unwrap_or_default();
+
+    if let Some(interpreter) = first_line.strip_prefix("#!") {
+        return [
+            ("python", "python"),
+            ("node", "javascript"),
+            ("ruby", "ruby"),
+            ("php", "php"),
+            ("bash", "bash"),
+            ("zsh", "bash"),
+            ("/sh", "bash"),
+        ]
+        .into_iter()
+        .find(|(pattern, _)| interpreter.contains(pattern))
+        .map(|(_, language)| language);
+    }
+
+    let lowercase = first_line.to_ascii_lowercase();
+    if lowercase.starts_with("<?php") {
+        Some("php")
+    } else if lowercase.starts_with("<?xml") {
+        Some("xml")
+    } else if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        Some("html")
+    } else {
+        None
+    }
+}
+
+fn is_json(content: &str) -> bool {
+    let trimmed = content.trim_start();
+    (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(content).is_ok()
+}
+
+fn detect_by_hints(content: &str) -> Option<&'static str> {
+    HINTS
+        .iter()
+        .map(|(language, hints)| {
+            let score = hints.iter().filter(|hint| content.contains(*hint)).count();
+            (*language, score)
+        })
+        .filter(|(_, score)| *score >= 2)
+        // the first of the languages with the highest score wins
+        .fold(None, |best: Option<(&str, usize)>, candidate| match best {
+            Some(best) if best.1 >= candidate.1 => Some(best),
+            _ => Some(candidate),
+        })
+        .map(|(language, _)| language)
+}
+
+impl FromStr for Language {
+    type Err = ClipError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        if raw.trim().is_empty() {
+            Ok(Self(None))
+        } else {
+            Self::new(raw)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Language {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(raw) => Self::from_str(&raw).map_err(serde::de::Error::custom),
+            None => Ok(Self(None)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Language {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Language;
+
+    #[test]
+    fn normalizes_aliases() {
+        assert_eq!(Language::new("RS").unwrap().as_deref(), Some("rust"));
+        assert_eq!(Language::new("c++").unwrap().as_deref(), Some("cpp"));
+        assert_eq!("".parse::<Language>().unwrap(), Language::default());
+        assert!(Language::new("cobol").is_err());
+    }
+
+    #[test]
+    fn never_serves_markup_as_markup() {
+        assert_eq!(Language::new("html").unwrap().mime_type(), "text/plain");
+        assert_eq!(Language::new("xml").unwrap().mime_type(), "text/plain");
+        assert_eq!(
+            Language::new("json").unwrap().mime_type(),
+            "application/json"
+        );
+        assert_eq!(Language::default().mime_type(), "text/plain");
+        assert_eq!(Language::new("py").unwrap().extension(), "py");
+    }
+
+    #[test]
+    fn detects_languages() {
+        let detect = |content: &str| Language::detect(content).into_inner();
+
+        assert_eq!(
+            detect("use std::io;\n\npub fn main() -> io::Result<()> {\n    let mut x = 1;\n}"),
+            Some("rust".to_owned())
+        );
+        assert_eq!(
+            detect("import os\n\ndef main():\n    print(os.getcwd())\n"),
+            Some("python".to_owned())
+        );
+        assert_eq!(detect("#!/usr/bin/env bash\nls"), Some("bash".to_owned()));
+        assert_eq!(detect(r#"{"a": [1, 2]}"#), Some("json".to_owned()));
+        assert_eq!(
+            detect("SELECT id FROM clips WHERE views > 10"),
+            Some("sql".to_owned())
+        );
+        assert_eq!(detect("remember to buy milk"), None);
+    }
+}
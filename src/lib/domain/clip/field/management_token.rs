@@ -0,0 +1,81 @@
+use crate::domain::clip::ClipError;
+use base64::{engine::general_purpose, Engine as _};
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use subtle::ConstantTimeEq;
+
+/// Secret handed out once to the creator of a clip, required to modify it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ManagementToken(String);
+
+impl ManagementToken {
+    pub fn new() -> Self {
+        let bytes: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        Self(general_purpose::URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+
+    /// the token carries enough entropy on its own, so a plain digest is sufficient
+    pub fn hash(&self) -> ManagementTokenHash {
+        ManagementTokenHash::new(format!("{:x}", Sha256::digest(self.0.as_bytes())))
+    }
+}
+
+impl Default for ManagementToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for ManagementToken {
+    type Err = ClipError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let token = s.trim();
+        if token.is_empty() {
+            Err(ClipError::InvalidToken("empty management token".to_owned()))
+        } else {
+            Ok(Self(token.to_owned()))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for ManagementToken {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+}
+
+/// Management token as it is stored in the database.
+///
+/// Clips created before tokens were introduced have none and can only be
+/// managed with an admin API key.
+#[derive(Clone, Debug, Default)]
+pub struct ManagementTokenHash(Option<String>);
+
+impl ManagementTokenHash {
+    pub fn new<T: Into<Option<String>>>(hash: T) -> Self {
+        Self(hash.into())
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+
+    pub fn verify(&self, token: &ManagementToken) -> bool {
+        match (&self.0, token.hash().0) {
+            (Some(stored), Some(given)) => stored.as_bytes().ct_eq(given.as_bytes()).into(),
+            _ => false,
+        }
+    }
+}
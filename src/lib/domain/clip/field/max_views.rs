@@ -0,0 +1,71 @@
+use crate::domain::clip::ClipError;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Number of successful reads after which a clip expires.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(try_from = "Option<u64>")]
+pub struct MaxViews(Option<u64>);
+
+impl MaxViews {
+    pub fn new<T: Into<Option<u64>>>(max_views: T) -> Result<Self, ClipError> {
+        match max_views.into() {
+            Some(0) => Err(ClipError::InvalidMaxViews(
+                "a clip must allow at least one view".to_owned(),
+            )),
+            max_views => Ok(Self(max_views)),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<u64> {
+        self.0
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn is_reached(&self, views: u64) -> bool {
+        match self.0 {
+            Some(max_views) => views >= max_views,
+            None => false,
+        }
+    }
+}
+
+impl TryFrom<Option<u64>> for MaxViews {
+    type Error = ClipError;
+
+    fn try_from(max_views: Option<u64>) -> Result<Self, Self::Error> {
+        Self::new(max_views)
+    }
+}
+
+impl FromStr for MaxViews {
+    type Err = ClipError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            Ok(Self(None))
+        } else {
+            let max_views = raw
+                .parse::<u64>()
+                .map_err(|e| ClipError::InvalidMaxViews(e.to_string()))?;
+            Self::new(max_views)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for MaxViews {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
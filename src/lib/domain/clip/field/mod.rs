@@ -2,11 +2,23 @@ mod id;
 pub use id::Id;
 
 mod short_code;
-pub use short_code::ShortCode;
+pub use short_code::{ShortCode, ShortCodeGenerator};
+
+mod vanity_code;
+pub use vanity_code::VanityCode;
 
 mod content;
 pub use content::Content;
 
+mod attachment;
+pub use attachment::Attachment;
+
+mod language;
+pub use language::{Language, LANGUAGES};
+
+mod rendering;
+pub use rendering::Rendering;
+
 mod title;
 pub use title::Title;
 
@@ -16,8 +28,23 @@ pub use created_at::CreatedAt;
 mod expires_at;
 pub use expires_at::ExpiresAt;
 
+mod max_views;
+pub use max_views::MaxViews;
+
 mod password;
 pub use password::Password;
 
+mod hashed_password;
+pub use hashed_password::HashedPassword;
+
+mod owner;
+pub use owner::Owner;
+
+mod visibility;
+pub use visibility::Visibility;
+
 mod views;
 pub use views::Views;
+
+mod management_token;
+pub use management_token::{ManagementToken, ManagementTokenHash};
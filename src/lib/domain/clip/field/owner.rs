@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// The API key a clip was created with, if any.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Owner(Option<Vec<u8>>);
+
+impl Owner {
+    pub fn new(owner: Option<Vec<u8>>) -> Self {
+        Self(owner)
+    }
+
+    pub fn into_inner(self) -> Option<Vec<u8>> {
+        self.0
+    }
+
+    pub fn is(&self, api_key: &[u8]) -> bool {
+        self.0.as_deref() == Some(api_key)
+    }
+}
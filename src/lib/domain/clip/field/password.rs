@@ -1,4 +1,7 @@
+use crate::domain::clip::field::HashedPassword;
 use crate::domain::clip::ClipError;
+use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+use argon2::Argon2;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
@@ -27,9 +30,27 @@ impl Password {
         self.0
     }
 
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
     pub fn has_password(&self) -> bool {
         self.0.is_some()
     }
+
+    /// hashes the password with Argon2id and a random salt
+    pub fn hash(&self) -> Result<HashedPassword, ClipError> {
+        match &self.0 {
+            Some(password) => {
+                let salt = SaltString::generate(&mut OsRng);
+                Argon2::default()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| HashedPassword::new(hash.to_string()))
+                    .map_err(|e| ClipError::Hash(e.to_string()))
+            }
+            None => Ok(HashedPassword::default()),
+        }
+    }
 }
 
 impl FromStr for Password {
@@ -45,4 +66,8 @@ impl<'r> FromFormField<'r> for Password {
         Ok(Self::new(field.value.to_owned())
             .map_err(|e| form::Error::validation(format!("{}", e)))?)
     }
+
+    fn default() -> Option<Self> {
+        Some(<Self as Default>::default())
+    }
 }
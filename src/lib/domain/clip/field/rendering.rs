@@ -0,0 +1,55 @@
+use crate::domain::clip::ClipError;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How a clip is shown on its page.
+///
+/// Plain clips are shown as is, code is highlighted in the language of the clip and markdown
+/// is rendered to sanitized HTML.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Rendering {
+    Plain,
+    #[default]
+    Code,
+    Markdown,
+}
+
+impl Rendering {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Code => "code",
+            Self::Markdown => "markdown",
+        }
+    }
+}
+
+impl FromStr for Rendering {
+    type Err = ClipError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim() {
+            "plain" => Ok(Self::Plain),
+            "" | "code" => Ok(Self::Code),
+            "markdown" => Ok(Self::Markdown),
+            other => Err(ClipError::InvalidRendering(format!(
+                "unknown rendering mode '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Rendering {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self::Code)
+    }
+}
@@ -2,32 +2,23 @@ use crate::domain::clip::ClipError;
 use derive_more::From;
 use rocket::request::FromParam;
 use rocket::{UriDisplayPath, UriDisplayQuery};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::str::FromStr;
 
-#[derive(
-    Debug, Clone, Deserialize, Serialize, From, UriDisplayPath, UriDisplayQuery, Hash, Eq, PartialEq,
-)]
-pub struct ShortCode(String);
-
-impl ShortCode {
-    pub fn new() -> Self {
-        use rand::prelude::*;
+pub const MAX_SHORT_CODE_LENGTH: usize = 32;
 
-        let allowed_chars = ['a', 'b', 'c', 'd', '1', '2', '3', '4'];
+const BASE62: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
 
-        let mut rng = thread_rng();
-        let mut short_code = String::with_capacity(10);
+fn is_short_code_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
 
-        for _ in 0..10 {
-            short_code.push(
-                *allowed_chars
-                    .choose(&mut rng)
-                    .expect("sampling array should have values"),
-            );
-        }
+#[derive(Debug, Clone, Serialize, From, UriDisplayPath, UriDisplayQuery, Hash, Eq, PartialEq)]
+pub struct ShortCode(String);
 
-        Self(short_code)
+impl ShortCode {
+    pub fn new() -> Self {
+        ShortCodeGenerator::default().generate()
     }
 
     pub fn as_str(&self) -> &str {
@@ -57,18 +48,123 @@ impl From<&str> for ShortCode {
     }
 }
 
+/// Accepts up to 32 ASCII letters, digits, `-` and `_`.
 impl FromStr for ShortCode {
     type Err = ClipError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > MAX_SHORT_CODE_LENGTH {
+            return Err(ClipError::InvalidShortCode(format!(
+                "short code must be between 1 and {} characters long",
+                MAX_SHORT_CODE_LENGTH
+            )));
+        }
+        if !s.chars().all(is_short_code_char) {
+            return Err(ClipError::InvalidShortCode(
+                "short code may only contain letters, digits, '-' and '_'".to_owned(),
+            ));
+        }
+
         Ok(Self(s.into()))
     }
 }
 
+impl<'de> Deserialize<'de> for ShortCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Self::from_str(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
 impl<'r> FromParam<'r> for ShortCode {
     type Error = &'r str;
 
     fn from_param(param: &'r str) -> Result<Self, Self::Error> {
-        Ok(ShortCode::from(param))
+        ShortCode::from_str(param).map_err(|_| param)
+    }
+}
+
+/// Draws random short codes from an alphabet, base62 by default.
+#[derive(Debug, Clone)]
+pub struct ShortCodeGenerator {
+    alphabet: Vec<char>,
+    length: usize,
+}
+
+impl ShortCodeGenerator {
+    pub fn new(alphabet: &str, length: usize) -> Result<Self, ClipError> {
+        let mut chars: Vec<char> = alphabet.chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+
+        if chars.len() < 2 {
+            return Err(ClipError::InvalidShortCode(
+                "alphabet needs at least two distinct characters".to_owned(),
+            ));
+        }
+        if !chars.iter().copied().all(is_short_code_char) {
+            return Err(ClipError::InvalidShortCode(
+                "alphabet may only contain letters, digits, '-' and '_'".to_owned(),
+            ));
+        }
+        if length == 0 || length > MAX_SHORT_CODE_LENGTH {
+            return Err(ClipError::InvalidShortCode(format!(
+                "length must be between 1 and {}",
+                MAX_SHORT_CODE_LENGTH
+            )));
+        }
+
+        Ok(Self {
+            alphabet: chars,
+            length,
+        })
+    }
+
+    pub fn generate(&self) -> ShortCode {
+        use rand::prelude::*;
+
+        let mut rng = thread_rng();
+        let short_code = (0..self.length)
+            .map(|_| {
+                *self
+                    .alphabet
+                    .choose(&mut rng)
+                    .expect("sampling array should have values")
+            })
+            .collect();
+
+        ShortCode(short_code)
+    }
+}
+
+impl Default for ShortCodeGenerator {
+    fn default() -> Self {
+        Self::new(BASE62, 8).expect("base62 is a valid alphabet")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn rejects_malformed_short_codes() {
+        assert!(ShortCode::from_str("aB3-x_9").is_ok());
+        assert!(ShortCode::from_str("").is_err());
+        assert!(ShortCode::from_str("has space").is_err());
+        assert!(ShortCode::from_str("../etc").is_err());
+        assert!(ShortCode::from_str(&"a".repeat(MAX_SHORT_CODE_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn generates_from_configured_alphabet() {
+        let generator = ShortCodeGenerator::new("xy", 12).unwrap();
+        let short_code = generator.generate();
+        assert_eq!(short_code.as_str().len(), 12);
+        assert!(short_code.as_str().chars().all(|c| c == 'x' || c == 'y'));
+
+        assert!(ShortCodeGenerator::new("a", 8).is_err());
+        assert!(ShortCodeGenerator::new("ab/", 8).is_err());
+        assert!(ShortCodeGenerator::new("ab", 0).is_err());
     }
 }
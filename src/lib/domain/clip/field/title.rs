@@ -49,4 +49,8 @@ impl<'r> FromFormField<'r> for Title {
             Ok(Self::new(field.value.to_owned()))
         }
     }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
 }
@@ -0,0 +1,108 @@
+use crate::domain::clip::field::ShortCode;
+use crate::domain::clip::ClipError;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::str::FromStr;
+
+pub const MIN_VANITY_CODE_LENGTH: usize = 3;
+
+/// Short codes that would shadow a route or be mistaken for one.
+pub const RESERVED_SHORT_CODES: &[&str] = &[
+    "admin",
+    "api",
+    "clip",
+    "clips",
+    "delete",
+    "download",
+    "edit",
+    "help",
+    "history",
+    "key",
+    "keys",
+    "login",
+    "logout",
+    "new",
+    "raw",
+    "revisions",
+    "search",
+    "static",
+    "upload",
+];
+
+/// Short code requested by the author instead of a generated one.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VanityCode(Option<ShortCode>);
+
+impl VanityCode {
+    pub fn new(short_code: &str) -> Result<Self, ClipError> {
+        let short_code = ShortCode::from_str(short_code)?;
+
+        if short_code.as_str().len() < MIN_VANITY_CODE_LENGTH {
+            return Err(ClipError::InvalidShortCode(format!(
+                "custom short codes need at least {} characters",
+                MIN_VANITY_CODE_LENGTH
+            )));
+        }
+        let lowercase = short_code.as_str().to_ascii_lowercase();
+        if RESERVED_SHORT_CODES.contains(&lowercase.as_str()) {
+            return Err(ClipError::InvalidShortCode(format!(
+                "'{}' is reserved",
+                short_code.as_str()
+            )));
+        }
+
+        Ok(Self(Some(short_code)))
+    }
+
+    pub fn into_inner(self) -> Option<ShortCode> {
+        self.0
+    }
+}
+
+impl FromStr for VanityCode {
+    type Err = ClipError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            Ok(Self(None))
+        } else {
+            Self::new(raw)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VanityCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(raw) => Self::from_str(&raw).map_err(serde::de::Error::custom),
+            None => Ok(Self(None)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for VanityCode {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn validates_vanity_codes() {
+        assert!(VanityCode::from_str("deploy-notes").is_ok());
+        assert!(VanityCode::from_str("").unwrap().into_inner().is_none());
+        assert!(VanityCode::from_str("ab").is_err());
+        assert!(VanityCode::from_str("deploy notes").is_err());
+        assert!(VanityCode::from_str("raw").is_err());
+        assert!(VanityCode::from_str("API").is_err());
+    }
+}
@@ -1,4 +1,3 @@
-use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 pub mod field;
@@ -14,6 +13,9 @@ pub enum ClipError {
     #[error("emoty content")]
     EmptyContent,
 
+    #[error("invalid view limit: {0}")]
+    InvalidMaxViews(String),
+
     #[error("invalid date: {0}")]
     InvalidDate(String),
 
@@ -25,16 +27,91 @@ pub enum ClipError {
 
     #[error("views parse error: {0}")]
     Views(#[from] std::num::TryFromIntError),
+
+    #[error("password hashing error: {0}")]
+    Hash(String),
+
+    #[error("invalid management token: {0}")]
+    InvalidToken(String),
+
+    #[error("invalid short code: {0}")]
+    InvalidShortCode(String),
+
+    #[error("invalid visibility: {0}")]
+    InvalidVisibility(String),
+
+    #[error("invalid attachment: {0}")]
+    InvalidAttachment(String),
+
+    #[error("invalid language: {0}")]
+    InvalidLanguage(String),
+
+    #[error("invalid rendering mode: {0}")]
+    InvalidRendering(String),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone)]
 pub struct Clip {
     pub id: field::Id,
     pub short_code: field::ShortCode,
+    /// the text of the clip, or the description of its attachment
     pub content: field::Content,
+    pub attachment: Option<field::Attachment>,
+    pub language: field::Language,
+    pub rendering: field::Rendering,
     pub title: field::Title,
     pub created_at: field::CreatedAt,
     pub expires_at: field::ExpiresAt,
-    pub password: field::Password,
+    pub password: field::HashedPassword,
     pub views: field::Views,
+    pub max_views: field::MaxViews,
+    pub management_token: field::ManagementTokenHash,
+    pub burn_after_read: bool,
+    pub owner: field::Owner,
+    pub visibility: field::Visibility,
+    /// numbered after the revisions in its history
+    pub revision: u64,
+    pub updated_at: Option<crate::Time>,
+}
+
+impl Clip {
+    /// when the clip was last updated, or created if it never was
+    pub fn last_modified(&self) -> crate::Time {
+        self.updated_at
+            .clone()
+            .unwrap_or_else(|| self.created_at.clone().into_inner())
+    }
+
+    /// private clips are only readable with the API key that created them
+    pub fn is_visible_to(&self, caller: Option<&[u8]>) -> bool {
+        match self.visibility {
+            field::Visibility::Private => caller.is_some_and(|caller| self.owner.is(caller)),
+            _ => true,
+        }
+    }
 }
+
+/// A prior version of a clip, recorded whenever its content, title or expiry is updated.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub revision: u64,
+    pub content: field::Content,
+    pub title: field::Title,
+    pub expires_at: field::ExpiresAt,
+    pub revised_at: crate::Time,
+}
+
+/// A clip matching a full-text search.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub short_code: field::ShortCode,
+    pub title: field::Title,
+    pub created_at: field::CreatedAt,
+    /// excerpt of the content, matches are wrapped in `SNIPPET_MATCH_START` and `SNIPPET_MATCH_END`
+    pub snippet: String,
+    /// lower is better
+    pub rank: f64,
+}
+
+pub const SNIPPET_MATCH_START: char = '\u{E000}';
+pub const SNIPPET_MATCH_END: char = '\u{E001}';
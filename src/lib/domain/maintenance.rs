@@ -1,19 +1,30 @@
-use crate::data::DatabasePool;
+use crate::data::{BlobStore, DatabasePool};
 use crate::service;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Handle;
 
 pub struct Maintenance;
 
 impl Maintenance {
-    pub fn spawn(pool: DatabasePool, handle: Handle) -> Self {
+    pub fn spawn(pool: Arc<DatabasePool>, storage: BlobStore, handle: Handle) -> Self {
         handle.spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(10));
             loop {
                 interval.tick().await;
-                if let Err(e) = service::action::delete_expires(&pool).await {
+                if let Err(e) = service::action::delete_expires(&*pool).await {
                     eprintln!("failed to delete expired clips: {}", e);
                 }
+                if let Err(e) = service::action::delete_exhausted(&*pool).await {
+                    eprintln!(
+                        "failed to delete clips that reached their view limit: {}",
+                        e
+                    );
+                }
+                if let Err(e) = service::action::delete_orphaned_attachments(&storage, &*pool).await
+                {
+                    eprintln!("failed to delete attachments of deleted clips: {}", e);
+                }
             }
         });
         Self
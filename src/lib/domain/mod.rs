@@ -1,5 +1,8 @@
 pub mod clip;
 pub mod maintenance;
+pub mod retention;
+pub mod stats;
 pub mod time;
 
-pub use clip::Clip;
+pub use clip::{Clip, Revision, SearchHit};
+pub use stats::Stats;
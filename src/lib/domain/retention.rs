@@ -0,0 +1,66 @@
+use crate::domain::clip::field::ExpiresAt;
+use crate::domain::time::{format_duration, Time};
+use chrono::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RetentionError {
+    #[error("clips may not be kept longer than {0}")]
+    TooLong(String),
+
+    #[error("default retention of {0} exceeds the maximum of {1}")]
+    DefaultExceedsMax(String, String),
+}
+
+/// Instance wide limits on how long clips are kept.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    /// applied to clips created without an expiry date
+    pub default_ttl: Option<Duration>,
+    /// the furthest into the future a clip may expire
+    pub max_ttl: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    pub fn new(
+        default_ttl: Option<Duration>,
+        max_ttl: Option<Duration>,
+    ) -> Result<Self, RetentionError> {
+        match (default_ttl, max_ttl) {
+            (Some(default_ttl), Some(max_ttl)) if default_ttl > max_ttl => {
+                Err(RetentionError::DefaultExceedsMax(
+                    format_duration(default_ttl),
+                    format_duration(max_ttl),
+                ))
+            }
+            _ => Ok(Self {
+                default_ttl,
+                max_ttl,
+            }),
+        }
+    }
+
+    /// fills in the default expiry and rejects expiry dates beyond the maximum
+    pub fn apply(&self, expires_at: ExpiresAt) -> Result<ExpiresAt, RetentionError> {
+        let expires_at = match expires_at.into_inner() {
+            Some(time) => time,
+            None => match self.default_ttl.or(self.max_ttl) {
+                Some(ttl) => Time::from_now(ttl).ok_or_else(|| self.too_long())?,
+                None => return Ok(ExpiresAt::default()),
+            },
+        };
+
+        match self.max_ttl.and_then(Time::from_now) {
+            Some(limit) if expires_at.timestamp() > limit.timestamp() => Err(self.too_long()),
+            _ => Ok(ExpiresAt::new(Some(expires_at))),
+        }
+    }
+
+    fn too_long(&self) -> RetentionError {
+        RetentionError::TooLong(
+            self.max_ttl
+                .map(format_duration)
+                .unwrap_or_else(|| "forever".to_owned()),
+        )
+    }
+}
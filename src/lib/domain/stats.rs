@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+/// Counts describing the contents of an instance.
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub clips: u64,
+    /// clips past their expiry that have not been purged yet
+    pub expired_clips: u64,
+    pub attachments: u64,
+    /// bytes of all attachments, wherever they are stored
+    pub attachment_bytes: u64,
+    pub views: u64,
+    pub api_keys: u64,
+}
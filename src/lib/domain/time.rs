@@ -1,4 +1,5 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::domain::clip::ClipError;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use derive_more::From;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
@@ -18,12 +19,26 @@ impl Time {
     pub fn from_naive_utc(datetime: NaiveDateTime) -> Self {
         Time(DateTime::from_naive_utc_and_offset(datetime, Utc))
     }
+
+    /// the point in time `duration` from now, if it can be represented
+    pub fn from_now(duration: Duration) -> Option<Self> {
+        Utc::now().checked_add_signed(duration).map(Time)
+    }
+
+    pub fn is_past(&self) -> bool {
+        self.0 <= Utc::now()
+    }
 }
 
 impl FromStr for Time {
     type Err = chrono::ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // RFC 3339, e.g. 2024-01-01T12:30:00+02:00
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(time.with_timezone(&Utc).into());
+        }
+
         // YYYY-MM-DD
         match format!("{}T00:00:00Z", s).parse::<DateTime<Utc>>() {
             Ok(time) => Ok(time.into()), //time.into() is provided by From macro from derive_more and is happen automatically to convert to Self
@@ -31,3 +46,59 @@ impl FromStr for Time {
         }
     }
 }
+
+/// Parses a relative duration made of `<number><unit>` pairs, such as `10m`, `1h30m` or `7d`.
+///
+/// Supported units are `s`, `m`, `h`, `d` and `w`.
+pub fn parse_duration(raw: &str) -> Result<Duration, ClipError> {
+    let invalid = || ClipError::InvalidDate(format!("invalid duration '{}'", raw));
+
+    let mut seconds: i64 = 0;
+    let mut digits = String::new();
+
+    for c in raw.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount = digits.parse::<i64>().map_err(|_| invalid())?;
+        digits.clear();
+
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|part| seconds.checked_add(part))
+            .ok_or_else(invalid)?;
+    }
+
+    if !digits.is_empty() || seconds == 0 || seconds > Duration::max_value().num_seconds() {
+        return Err(invalid());
+    }
+
+    Ok(Duration::seconds(seconds))
+}
+
+/// Formats a duration in the largest unit accepted by `parse_duration` that represents it exactly.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    let units = [
+        ('w', 7 * 24 * 60 * 60),
+        ('d', 24 * 60 * 60),
+        ('h', 60 * 60),
+        ('m', 60),
+    ];
+
+    units
+        .iter()
+        .find(|(_, unit)| seconds % unit == 0)
+        .map(|(suffix, unit)| format!("{}{}", seconds / unit, suffix))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
@@ -5,12 +5,15 @@ pub mod web;
 
 pub use data::DataError;
 pub use domain::clip::field::ShortCode;
+use domain::clip::field::ShortCodeGenerator;
 pub use domain::clip::{Clip, ClipError};
 use domain::maintenance::Maintenance;
+use domain::retention::RetentionPolicy;
 pub use domain::time::Time;
 pub use service::ServiceError;
 
-use data::AppDatabase;
+use data::{AppDatabase, BlobStore};
+use rocket::data::{ByteUnit, Limits, ToByteUnit};
 use rocket::fs::FileServer;
 use rocket::{Build, Rocket};
 use web::renderer::Renderer;
@@ -21,19 +24,33 @@ pub struct RocketConfig {
     pub database: AppDatabase,
     pub views: Views,
     pub maintenance: Maintenance,
+    pub retention: RetentionPolicy,
+    pub short_codes: ShortCodeGenerator,
+    pub storage: BlobStore,
+    pub max_upload_size: ByteUnit,
 }
 
 pub fn rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
+    let limits = Limits::default()
+        .limit("file", config.max_upload_size)
+        // leaves room for the other fields of the upload form
+        .limit("data-form", config.max_upload_size + 1.mebibytes());
+
+    rocket::custom(rocket::Config::figment().merge(("limits", limits)))
         .manage::<AppDatabase>(config.database)
         .manage::<Renderer>(config.renderer)
         .manage::<Views>(config.views)
         .manage::<Maintenance>(config.maintenance)
+        .manage::<RetentionPolicy>(config.retention)
+        .manage::<ShortCodeGenerator>(config.short_codes)
+        .manage::<BlobStore>(config.storage)
         .mount("/", web::http::routes())
         .mount("/api/clip", web::api::routes())
+        .mount("/api/keys", web::api::key_routes())
         .mount("/static", FileServer::from("static"))
         .register("/", web::http::catcher::catchers())
         .register("/api/clip", web::api::catcher::catchers())
+        .register("/api/keys", web::api::catcher::catchers())
 }
 
 #[cfg(test)]
@@ -1,14 +1,22 @@
-use crate::data::{query, DatabasePool, Transaction};
+use crate::data::{model, query, BlobStore, ClipTransaction, DatabasePool};
+use crate::domain::clip::field::{
+    Attachment, Content, ExpiresAt, Language, ManagementToken, ShortCodeGenerator, Views,
+    Visibility,
+};
+use crate::domain::retention::RetentionPolicy;
+use crate::domain::{Revision, SearchHit, Stats};
 use crate::service::ask;
-use crate::web::api::ApiKey;
-use crate::{Clip, ServiceError, ShortCode};
-use std::convert::TryInto;
+use crate::web::api::{ApiKey, ApiKeyDetails, RawApiKey};
+use crate::{Clip, ClipError, ServiceError, ShortCode};
+use std::convert::{TryFrom, TryInto};
 
-pub async fn begin_transaction(pool: &DatabasePool) -> Result<Transaction<'_>, ServiceError> {
+pub async fn begin_transaction(
+    pool: &DatabasePool,
+) -> Result<Box<dyn ClipTransaction>, ServiceError> {
     Ok(pool.begin().await?)
 }
 
-pub async fn end_transaction(transaction: Transaction<'_>) -> Result<(), ServiceError> {
+pub async fn end_transaction(transaction: Box<dyn ClipTransaction>) -> Result<(), ServiceError> {
     Ok(transaction.commit().await?)
 }
 
@@ -17,47 +25,454 @@ pub async fn increase_views(
     views: u32,
     pool: &DatabasePool,
 ) -> Result<(), ServiceError> {
-    Ok(query::increase_views(short_code, views, pool).await?)
+    Ok(pool.increase_views(short_code, views).await?)
 }
 
-pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    Ok(query::new_clip(req, pool).await?.try_into()?)
+/// creates a clip and returns it along with its management token, which is not stored in plain
+pub async fn new_clip(
+    req: ask::NewClip,
+    owner: Option<&ApiKey>,
+    retention: &RetentionPolicy,
+    short_codes: &ShortCodeGenerator,
+    pool: &DatabasePool,
+) -> Result<(Clip, ManagementToken), ServiceError> {
+    create_clip(req, None, owner, retention, short_codes, pool).await
 }
 
-pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    Ok(query::update_clip(req, pool).await?.try_into()?)
+/// creates a clip carrying a file, whose bytes are kept in `storage`
+///
+/// Files cannot expire on reading, their clip page and the download would each count a view.
+pub async fn upload_clip(
+    req: ask::NewAttachment,
+    owner: Option<&ApiKey>,
+    retention: &RetentionPolicy,
+    short_codes: &ShortCodeGenerator,
+    storage: &BlobStore,
+    pool: &DatabasePool,
+) -> Result<(Clip, ManagementToken), ServiceError> {
+    if req.burn_after_read || req.max_views.is_limited() {
+        return Err(ClipError::InvalidAttachment(
+            "files cannot be burned after reading or limited to a number of views".to_owned(),
+        )
+        .into());
+    }
+    let attachment = Attachment::new(
+        &req.filename,
+        req.mime_type.as_deref(),
+        req.data.len() as u64,
+    )?;
+    let content = match req.content {
+        Some(content) => content,
+        None => Content::new(attachment.filename())?,
+    };
+    let clip = ask::NewClip {
+        content,
+        title: req.title,
+        exprires_at: req.expires_at,
+        password: req.password,
+        max_views: Default::default(),
+        burn_after_read: false,
+        short_code: req.short_code,
+        visibility: req.visibility,
+        language: Default::default(),
+        rendering: Default::default(),
+    };
+
+    let (clip, token) =
+        create_clip(clip, Some(&attachment), owner, retention, short_codes, pool).await?;
+    let id: String = clip.id.clone().into_inner().into();
+    if let Err(e) = storage.put(&id, &req.data, pool).await {
+        pool.delete_clip(&clip.short_code).await?;
+        return Err(e.into());
+    }
+    Ok((clip, token))
 }
 
+async fn create_clip(
+    mut req: ask::NewClip,
+    attachment: Option<&Attachment>,
+    owner: Option<&ApiKey>,
+    retention: &RetentionPolicy,
+    short_codes: &ShortCodeGenerator,
+    pool: &DatabasePool,
+) -> Result<(Clip, ManagementToken), ServiceError> {
+    req.exprires_at = retention.apply(req.exprires_at)?;
+    if req.visibility == Visibility::Private && owner.is_none() {
+        return Err(ClipError::InvalidVisibility(
+            "private clips have to be created with an API key".to_owned(),
+        )
+        .into());
+    }
+    if attachment.is_none() && !req.language.is_set() {
+        req.language = Language::detect(req.content.as_str());
+    }
+    let token = ManagementToken::default();
+    let mut req = model::NewClip::try_from(req)?
+        .with_management_token(&token)
+        .with_owner(owner);
+    if let Some(attachment) = attachment {
+        req = req.with_attachment(attachment)?;
+    }
+    Ok((pool.new_clip(req, short_codes).await?.try_into()?, token))
+}
+
+pub async fn update_clip(
+    mut req: ask::UpdateClip,
+    auth: ask::Authorization,
+    retention: &RetentionPolicy,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    authorize(&req.short_code, &auth, pool).await?;
+    req.exprires_at = retention.apply(req.exprires_at)?;
+    if !req.language.is_set() {
+        req.language = Language::detect(req.content.as_str());
+    }
+    let req = model::UpdateClip::try_from(req)?;
+    Ok(pool.update_clip(req).await?.try_into()?)
+}
+
+/// clearing the expiry date falls back to the default retention, if any
+pub async fn patch_clip(
+    short_code: ShortCode,
+    mut req: ask::PatchClip,
+    auth: ask::Authorization,
+    retention: &RetentionPolicy,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    authorize(&short_code, &auth, pool).await?;
+    if req.visibility == Some(Visibility::Private) {
+        let clip: Clip = pool.get_clip(short_code.clone().into()).await?.try_into()?;
+        if clip.owner.into_inner().is_none() {
+            return Err(ClipError::InvalidVisibility(
+                "clips created without an API key cannot be private".to_owned(),
+            )
+            .into());
+        }
+    }
+    if matches!(&req.language, Some(language) if !language.is_set()) {
+        let language = match &req.content {
+            Some(content) => Language::detect(content.as_str()),
+            None => {
+                let clip: Clip = pool.get_clip(short_code.clone().into()).await?.try_into()?;
+                Language::detect(clip.content.as_str())
+            }
+        };
+        req.language = Some(language);
+    }
+    req.expires_at = match req.expires_at {
+        ask::Patch::Keep => ask::Patch::Keep,
+        ask::Patch::Clear => match retention.apply(ExpiresAt::default())?.into_inner() {
+            Some(time) => ask::Patch::Set(ExpiresAt::new(time)),
+            None => ask::Patch::Clear,
+        },
+        ask::Patch::Set(expires_at) => ask::Patch::Set(retention.apply(expires_at)?),
+    };
+    let req = model::PatchClip::try_from((short_code, req))?;
+    Ok(pool.patch_clip(req).await?.try_into()?)
+}
+
+pub async fn delete_clip(
+    short_code: ShortCode,
+    auth: ask::Authorization,
+    storage: &BlobStore,
+    pool: &DatabasePool,
+) -> Result<query::DeletionStatus, ServiceError> {
+    let clip: Clip = match pool.get_clip(short_code.clone().into()).await {
+        Ok(clip) => clip.try_into()?,
+        Err(e) => match ServiceError::from(e) {
+            ServiceError::NotFound => return Ok(query::DeletionStatus::NotFound),
+            e => return Err(e),
+        },
+    };
+    match authorize(&short_code, &auth, pool).await {
+        Err(ServiceError::NotFound) => return Ok(query::DeletionStatus::NotFound),
+        other => other?,
+    }
+    let status = pool.delete_clip(&short_code).await?;
+    if clip.attachment.is_some() {
+        let id: String = clip.id.into_inner().into();
+        storage.delete(&id, pool).await?;
+    }
+    Ok(status)
+}
+
+async fn authorize(
+    short_code: &ShortCode,
+    auth: &ask::Authorization,
+    pool: &DatabasePool,
+) -> Result<(), ServiceError> {
+    match auth {
+        ask::Authorization::Admin => Ok(()),
+        ask::Authorization::Token(token) => {
+            let clip: Clip = pool.get_clip(short_code.clone().into()).await?.try_into()?;
+            if clip.management_token.verify(token) {
+                Ok(())
+            } else {
+                Err(ServiceError::PermissionError(
+                    "Invalid management token".to_owned(),
+                ))
+            }
+        }
+    }
+}
+
+/// fetches a clip after checking its password, clips marked `burn_after_read` are deleted
+/// and view limits are enforced in the same transaction so neither can be read too often
 pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
     let user_password = req.password.clone();
-    let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
+    let caller = req.caller.clone();
+    let mut transaction = begin_transaction(pool).await?;
+    let mut clip: Clip = transaction.get_clip(req.into()).await?.try_into()?;
+
+    check_visibility(&clip, caller.as_ref())?;
+
+    if clip.max_views.is_reached(clip.views.clone().into_inner()) {
+        return Err(ServiceError::NotFound);
+    }
+
     if clip.password.has_password() {
-        if clip.password == user_password {
-            Ok(clip)
-        } else {
-            Err(ServiceError::PermissionError("Invalid password".to_owned()))
+        if !clip.password.verify(&user_password) {
+            return Err(ServiceError::PermissionError("Invalid password".to_owned()));
+        }
+        if clip.password.is_legacy() {
+            // rows created before hashing was introduced are upgraded on first access
+            let hash = user_password.hash()?.into_inner();
+            transaction.update_password(&clip.short_code, hash).await?;
+        }
+    }
+
+    if clip.max_views.is_limited() {
+        // views of limited clips are counted here instead of by the lazy `Views` counter
+        if !transaction.consume_view(&clip.short_code).await? {
+            return Err(ServiceError::NotFound);
+        }
+        clip.views = Views::new(clip.views.into_inner() + 1);
+    }
+
+    if clip.burn_after_read {
+        // another reader may have consumed the clip since it was fetched
+        if let query::DeletionStatus::NotFound = transaction.delete_clip(&clip.short_code).await? {
+            return Err(ServiceError::NotFound);
         }
+    }
+
+    end_transaction(transaction).await?;
+    Ok(clip)
+}
+
+/// checks access to a clip like `get_clip` without counting a view or burning it
+pub async fn peek_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
+    let user_password = req.password.clone();
+    let caller = req.caller.clone();
+    let clip: Clip = pool.get_clip(req.into()).await?.try_into()?;
+
+    check_visibility(&clip, caller.as_ref())?;
+    if clip.max_views.is_reached(clip.views.clone().into_inner()) {
+        return Err(ServiceError::NotFound);
+    }
+    if clip.password.has_password() && !clip.password.verify(&user_password) {
+        return Err(ServiceError::PermissionError("Invalid password".to_owned()));
+    }
+
+    Ok(clip)
+}
+
+/// fetches the file of a clip, checking access and counting the read like `get_clip`
+pub async fn get_attachment(
+    req: ask::GetClip,
+    storage: &BlobStore,
+    pool: &DatabasePool,
+) -> Result<(Clip, Attachment, Vec<u8>), ServiceError> {
+    let clip = get_clip(req, pool).await?;
+    let attachment = clip.attachment.clone().ok_or(ServiceError::NotFound)?;
+    let id: String = clip.id.clone().into_inner().into();
+    let data = storage.get(&id, pool).await?;
+    Ok((clip, attachment, data))
+}
+
+/// returns a clip along with its prior versions
+///
+/// Clips that expire on reading have no browsable history, it would bypass their view limit.
+pub async fn get_revisions(
+    req: ask::GetClip,
+    pool: &DatabasePool,
+) -> Result<(Clip, Vec<Revision>), ServiceError> {
+    let clip = readable_clip(req, pool).await?;
+    let revisions = pool
+        .get_revisions(&clip.short_code)
+        .await?
+        .into_iter()
+        .map(Revision::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((clip, revisions))
+}
+
+pub async fn get_revision(
+    req: ask::GetClip,
+    revision: u64,
+    pool: &DatabasePool,
+) -> Result<Revision, ServiceError> {
+    let clip = readable_clip(req, pool).await?;
+    let revision = i64::try_from(revision).map_err(|_| ServiceError::NotFound)?;
+    Ok(pool
+        .get_revision(&clip.short_code, revision)
+        .await?
+        .try_into()?)
+}
+
+fn check_visibility(clip: &Clip, caller: Option<&ApiKey>) -> Result<(), ServiceError> {
+    let caller = caller.map(|api_key| api_key.clone().into_inner());
+    if clip.is_visible_to(caller.as_deref()) {
+        Ok(())
+    } else {
+        Err(ServiceError::Forbidden("this clip is private".to_owned()))
+    }
+}
+
+async fn readable_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
+    let user_password = req.password.clone();
+    let caller = req.caller.clone();
+    let clip: Clip = pool.get_clip(req.into()).await?.try_into()?;
+
+    check_visibility(&clip, caller.as_ref())?;
+    if clip.burn_after_read || clip.max_views.is_limited() {
+        return Err(ServiceError::NotFound);
+    }
+    if clip.password.has_password() && !clip.password.verify(&user_password) {
+        return Err(ServiceError::PermissionError("Invalid password".to_owned()));
+    }
+
+    Ok(clip)
+}
+
+/// lists the clips created with an API key, one page at a time
+pub async fn list_clips(
+    owner: &ApiKey,
+    req: ask::ListClips,
+    pool: &DatabasePool,
+) -> Result<ask::Page<Clip>, ServiceError> {
+    let sort = req.sort;
+    let page_size = req.page_size() as usize;
+    let mut clips = pool
+        .list_clips((owner, req).into())
+        .await?
+        .into_iter()
+        .map(Clip::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let next_cursor = if clips.len() > page_size {
+        clips.truncate(page_size);
+        clips.last().map(|clip| ask::Cursor {
+            sort,
+            value: match sort {
+                ask::ClipSort::CreatedAt => clip.created_at.clone().into_inner().timestamp(),
+                ask::ClipSort::Views => clip.views.clone().into_inner() as i64,
+            },
+            id: clip.id.clone().into_inner().into(),
+        })
     } else {
-        Ok(clip)
+        None
+    };
+
+    Ok(ask::Page {
+        items: clips,
+        next_cursor,
+    })
+}
+
+pub async fn search_clips(
+    caller: &ApiKey,
+    req: ask::SearchClips,
+    pool: &DatabasePool,
+) -> Result<Vec<SearchHit>, ServiceError> {
+    if req.query.trim().is_empty() {
+        return Ok(vec![]);
     }
+
+    Ok(pool
+        .search_clips((caller, req).into())
+        .await?
+        .into_iter()
+        .map(SearchHit::try_from)
+        .collect::<Result<_, _>>()?)
 }
 
-pub async fn generate_api_key(pool: &DatabasePool) -> Result<ApiKey, ServiceError> {
-    let api_key = ApiKey::default();
-    Ok(query::generate_api_key(api_key, pool).await?)
+/// issues a key, the returned key is the only copy of its secret
+pub async fn generate_api_key(
+    req: ask::NewApiKey,
+    pool: &DatabasePool,
+) -> Result<RawApiKey, ServiceError> {
+    let raw_key = RawApiKey::generate();
+    pool.new_api_key((&raw_key, req).into()).await?;
+    Ok(raw_key)
 }
 
 pub async fn revoke_api_key(
-    api_key: ApiKey,
+    prefix: &str,
     pool: &DatabasePool,
 ) -> Result<query::RevocationStatus, ServiceError> {
-    Ok(query::revoke_api_key(api_key, pool).await?)
+    Ok(pool.revoke_api_key(prefix).await?)
+}
+
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<ApiKeyDetails>, ServiceError> {
+    Ok(pool
+        .list_api_keys()
+        .await?
+        .into_iter()
+        .map(ApiKeyDetails::from)
+        .collect())
 }
 
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool, ServiceError> {
-    Ok(query::api_key_is_valid(api_key, pool).await?)
+/// looks up the key a client presented, rejecting expired keys
+pub async fn authenticate_api_key(
+    raw_key: &RawApiKey,
+    pool: &DatabasePool,
+) -> Result<ApiKey, ServiceError> {
+    // keys issued before hashing are found by the key itself until they are upgraded
+    let legacy = match raw_key.legacy_bytes() {
+        Some(bytes) => pool.get_legacy_api_key(&bytes).await?,
+        None => None,
+    };
+    let api_key = match legacy {
+        Some(api_key) => api_key,
+        None => pool.get_api_key(&raw_key.prefix()).await?,
+    };
+    if !api_key.verify(raw_key) {
+        return Err(ServiceError::NotFound);
+    }
+    if api_key.is_expired() {
+        return Err(ServiceError::PermissionError("API key expired".to_owned()));
+    }
+    // keys issued before hashing are upgraded on first use
+    let api_key = match api_key.is_legacy() {
+        true => {
+            let prefix = raw_key.prefix();
+            let id = pool
+                .upgrade_api_key(api_key.id(), &prefix, &raw_key.hash())
+                .await?;
+            api_key.upgraded(id, prefix)
+        }
+        false => api_key,
+    };
+    pool.touch_api_key(api_key.id()).await?;
+    Ok(api_key.into())
 }
 
 pub async fn delete_expires(pool: &DatabasePool) -> Result<u64, ServiceError> {
-    Ok(query::delete_expired(pool).await?)
+    Ok(pool.delete_expired().await?)
+}
+
+pub async fn delete_exhausted(pool: &DatabasePool) -> Result<u64, ServiceError> {
+    Ok(pool.delete_exhausted().await?)
+}
+
+pub async fn stats(pool: &DatabasePool) -> Result<Stats, ServiceError> {
+    Ok(pool.stats().await?.into())
+}
+
+pub async fn delete_orphaned_attachments(
+    storage: &BlobStore,
+    pool: &DatabasePool,
+) -> Result<u64, ServiceError> {
+    Ok(storage.delete_orphans(pool).await?)
 }
@@ -1,6 +1,8 @@
 use crate::domain::clip::field;
+use crate::web::api::{ApiKey, Scope};
 use crate::ShortCode;
-use serde::{Deserialize, Serialize};
+use rocket::FromFormField;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct NewClip {
@@ -8,6 +10,36 @@ pub struct NewClip {
     pub title: field::Title,
     pub exprires_at: field::ExpiresAt,
     pub password: field::Password,
+    #[serde(default)]
+    pub max_views: field::MaxViews,
+    #[serde(default)]
+    pub burn_after_read: bool,
+    #[serde(default)]
+    pub short_code: field::VanityCode,
+    #[serde(default)]
+    pub visibility: field::Visibility,
+    /// detected from the content when not given
+    #[serde(default)]
+    pub language: field::Language,
+    #[serde(default)]
+    pub rendering: field::Rendering,
+}
+
+/// A clip carrying a file, `content` describes it and defaults to the filename.
+#[derive(Debug)]
+pub struct NewAttachment {
+    pub content: Option<field::Content>,
+    pub title: field::Title,
+    pub expires_at: field::ExpiresAt,
+    pub password: field::Password,
+    pub max_views: field::MaxViews,
+    pub burn_after_read: bool,
+    pub short_code: field::VanityCode,
+    pub visibility: field::Visibility,
+    pub filename: String,
+    /// as sent by the client, guessed from the filename when missing
+    pub mime_type: Option<String>,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -17,12 +49,109 @@ pub struct UpdateClip {
     pub exprires_at: field::ExpiresAt,
     pub password: field::Password,
     pub short_code: field::ShortCode,
+    /// detected from the content when not given
+    #[serde(default)]
+    pub language: field::Language,
+    #[serde(default)]
+    pub rendering: field::Rendering,
+}
+
+/// A single field of a partial update.
+///
+/// A missing field keeps the current value, an explicit `null` clears it.
+#[derive(Debug, Clone, Default)]
+pub enum Patch<T> {
+    #[default]
+    Keep,
+    Clear,
+    Set(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_keep(&self) -> bool {
+        matches!(self, Patch::Keep)
+    }
+}
+
+impl<T> From<Option<T>> for Patch<T> {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => Patch::Set(value),
+            None => Patch::Clear,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // only called when the field is present, missing fields fall back to `Keep`
+        Ok(Option::<T>::deserialize(deserializer)?.into())
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Patch::Set(value) => serializer.serialize_some(value),
+            _ => serializer.serialize_none(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PatchClip {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<field::Content>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub title: Patch<field::Title>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub expires_at: Patch<field::ExpiresAt>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub password: Patch<field::Password>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<field::Visibility>,
+    /// an empty language detects it again from the content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<field::Language>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rendering: Option<field::Rendering>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewApiKey {
+    /// helps telling keys apart, the prefix of the key identifies it
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "Scope::defaults")]
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub expires_at: field::ExpiresAt,
+}
+
+impl Default for NewApiKey {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            scopes: Scope::defaults(),
+            expires_at: Default::default(),
+        }
+    }
+}
+
+/// Proof that the caller is allowed to modify a clip.
+#[derive(Debug, Clone)]
+pub enum Authorization {
+    Token(field::ManagementToken),
+    Admin,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetClip {
     pub short_code: ShortCode,
     pub password: field::Password,
+    /// the API key of the reader, required for private clips
+    #[serde(skip)]
+    pub caller: Option<ApiKey>,
 }
 
 impl GetClip {
@@ -30,6 +159,7 @@ impl GetClip {
         Self {
             short_code: ShortCode::from(short_code),
             password: field::Password::default(),
+            caller: None,
         }
     }
 }
@@ -39,6 +169,7 @@ impl From<ShortCode> for GetClip {
         Self {
             short_code,
             password: field::Password::default(),
+            caller: None,
         }
     }
 }
@@ -48,3 +179,170 @@ impl From<&str> for GetClip {
         Self::from_raw(short_code)
     }
 }
+
+/// Order of a clip listing, newest or most viewed first.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    FromFormField,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ClipSort {
+    #[default]
+    #[field(value = "created_at")]
+    CreatedAt,
+    #[field(value = "views")]
+    Views,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    FromFormField,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ExpiryStatus {
+    #[default]
+    #[field(value = "all")]
+    All,
+    #[field(value = "expired")]
+    Expired,
+    #[field(value = "unexpired")]
+    Unexpired,
+}
+
+/// Whose clips a listing shows, the caller's own or everyone's public clips.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    FromFormField,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ListScope {
+    #[default]
+    #[field(value = "mine")]
+    Mine,
+    #[field(value = "public")]
+    Public,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid cursor")]
+pub struct InvalidCursor;
+
+/// Position after the last clip of a page, opaque to clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub sort: ClipSort,
+    pub value: i64,
+    pub id: String,
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+        let raw = format!("{}:{}:{}", self.sort, self.value, self.id);
+        write!(f, "{}", URL_SAFE_NO_PAD.encode(raw))
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+        let raw = URL_SAFE_NO_PAD.decode(s).map_err(|_| InvalidCursor)?;
+        let raw = String::from_utf8(raw).map_err(|_| InvalidCursor)?;
+        let mut parts = raw.splitn(3, ':');
+        let (sort, value, id) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(sort), Some(value), Some(id)) => (sort, value, id),
+            _ => return Err(InvalidCursor),
+        };
+
+        Ok(Self {
+            sort: sort.parse().map_err(|_| InvalidCursor)?,
+            value: value.parse().map_err(|_| InvalidCursor)?,
+            id: id.to_owned(),
+        })
+    }
+}
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Clone, Default)]
+pub struct ListClips {
+    pub cursor: Option<Cursor>,
+    /// clamped to `1..=MAX_PAGE_SIZE`, `DEFAULT_PAGE_SIZE` when missing
+    pub limit: Option<u32>,
+    pub sort: ClipSort,
+    pub status: ExpiryStatus,
+    pub scope: ListScope,
+}
+
+impl ListClips {
+    pub fn page_size(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Cursor>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchClips {
+    pub query: String,
+    /// clamped to `1..=MAX_PAGE_SIZE`, `DEFAULT_PAGE_SIZE` when missing
+    pub limit: Option<u32>,
+}
+
+impl SearchClips {
+    pub fn page_size(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    /// Every word of the query has to match, FTS5 operators are matched literally.
+    pub fn match_expression(&self) -> String {
+        self.query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
@@ -2,6 +2,7 @@
 pub mod action;
 pub mod ask;
 
+use crate::domain::retention::RetentionError;
 use crate::{ClipError, DataError};
 
 #[derive(Debug, thiserror::Error)]
@@ -17,6 +18,15 @@ pub enum ServiceError {
 
     #[error("access denied: {0}")]
     PermissionError(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("conflict: {0}")]
+    Conflict(String),
+
+    #[error("retention policy: {0}")]
+    Retention(#[from] RetentionError),
 }
 
 impl From<DataError> for ServiceError {
@@ -26,6 +36,9 @@ impl From<DataError> for ServiceError {
                 sqlx::Error::RowNotFound => Self::NotFound,
                 other => Self::Data(DataError::Database(other)),
             },
+            DataError::ShortCodeTaken(_) => Self::Conflict(err.to_string()),
+            DataError::Storage(e) if e.kind() == std::io::ErrorKind::NotFound => Self::NotFound,
+            other => Self::Data(other),
         }
     }
 }
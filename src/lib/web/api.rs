@@ -1,20 +1,27 @@
-use crate::data::AppDatabase;
+use crate::data::query::{DeletionStatus, RevocationStatus};
+use crate::data::{AppDatabase, BlobStore};
+use crate::domain::clip::field::{ManagementToken, ShortCodeGenerator};
+use crate::domain::retention::RetentionPolicy;
 use crate::service;
-use crate::service::action;
-use crate::web::{Views, PASSWORD_COOKIE};
-use crate::ServiceError;
+use crate::service::{action, ask};
+use crate::web::conditional::{self, Conditional, Conditions, Representation, Validators};
+use crate::web::{form, password_from_cookie, Download};
+use crate::web::{ClipPage, ClipView, IssuedApiKey, RevisionView, SearchResult, Views};
+use crate::{ServiceError, ShortCode};
 use base64::{engine::general_purpose, Engine as _};
+use rocket::form::Form;
 use rocket::http::{CookieJar, Status};
 use rocket::request::{FromRequest, Outcome, Request};
-use rocket::serde::json::Json;
+use rocket::serde::json::{self, Json};
 use rocket::Responder;
 use rocket::State;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
 pub const API_KEY_HEADER: &str = "x-api-key";
+pub const MANAGEMENT_TOKEN_HEADER: &str = "x-management-token";
 
-#[derive(Responder, Debug, thiserror::Error, Serialize)]
+#[derive(Responder, Debug, Clone, thiserror::Error, Serialize)]
 pub enum ApiKeyError {
     #[error("API key not found")]
     #[response(status = 404, content_type = "json")]
@@ -23,39 +30,198 @@ pub enum ApiKeyError {
     #[error("invalid API key format")]
     #[response(status = 400, content_type = "json")]
     DecodeError(String),
+
+    #[error("invalid scope")]
+    #[response(status = 400, content_type = "json")]
+    InvalidScope(String),
+}
+
+/// What an API key may be used for, `admin` grants every other scope as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Scope {
+    #[serde(rename = "clip:read")]
+    ClipRead,
+    #[serde(rename = "clip:write")]
+    ClipWrite,
+    #[serde(rename = "clip:delete")]
+    ClipDelete,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ClipRead => "clip:read",
+            Self::ClipWrite => "clip:write",
+            Self::ClipDelete => "clip:delete",
+            Self::Admin => "admin",
+        }
+    }
+
+    /// the scopes of keys that are issued without naming any
+    pub fn defaults() -> Vec<Scope> {
+        vec![Self::ClipRead, Self::ClipWrite, Self::ClipDelete]
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ApiKeyError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim() {
+            "clip:read" => Ok(Self::ClipRead),
+            "clip:write" => Ok(Self::ClipWrite),
+            "clip:delete" => Ok(Self::ClipDelete),
+            "admin" => Ok(Self::Admin),
+            other => Err(ApiKeyError::InvalidScope(format!(
+                "unknown scope '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
+/// An API key that was presented with a request and verified.
+///
+/// Clips are owned by the id of the key, which is never handed out.
 #[derive(Debug, Clone)]
-pub struct ApiKey(Vec<u8>);
+pub struct ApiKey {
+    id: Vec<u8>,
+    prefix: String,
+    scopes: Vec<Scope>,
+}
 
 impl ApiKey {
-    pub fn to_base64(&self) -> String {
-        general_purpose::STANDARD.encode(self.0.as_slice())
+    pub fn new(id: Vec<u8>, prefix: String, scopes: Vec<Scope>) -> Self {
+        Self { id, prefix, scopes }
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        self.scopes.as_slice()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
     }
 
     pub fn into_inner(self) -> Vec<u8> {
-        self.0
+        self.id
+    }
+}
+
+/// What is known about an issued key, everything but its secret.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeyDetails {
+    pub prefix: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: crate::Time,
+    pub expires_at: Option<crate::Time>,
+    pub last_used_at: Option<crate::Time>,
+}
+
+const PREFIX_BYTES: usize = 6;
+const SECRET_BYTES: usize = 32;
+const LEGACY_KEY_BYTES: usize = 16;
+
+/// An API key as sent by a client, `<prefix>.<secret>`.
+///
+/// The prefix identifies the key in listings and logs, only a hash of the whole key is stored.
+/// Keys issued before hashing are 16 bytes in base64, their prefix comes from the hash so that it
+/// gives nothing of the key away.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RawApiKey(String);
+
+impl RawApiKey {
+    pub fn generate() -> Self {
+        use rand::RngCore;
+
+        let mut prefix = [0u8; PREFIX_BYTES];
+        let mut secret = [0u8; SECRET_BYTES];
+        rand::thread_rng().fill_bytes(&mut prefix);
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self(format!(
+            "{}.{}",
+            hex(&prefix),
+            general_purpose::URL_SAFE_NO_PAD.encode(secret)
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn prefix(&self) -> String {
+        match self.legacy_bytes() {
+            Some(_) => self.hash()[..PREFIX_BYTES * 2].to_owned(),
+            None => self.0.split('.').next().unwrap_or_default().to_owned(),
+        }
+    }
+
+    /// a fast hash is enough, the secret is random rather than chosen by a person
+    pub fn hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
     }
+
+    /// the raw bytes of a key issued before keys were hashed
+    pub fn legacy_bytes(&self) -> Option<Vec<u8>> {
+        general_purpose::STANDARD
+            .decode(self.0.as_str())
+            .ok()
+            .filter(|bytes| bytes.len() == LEGACY_KEY_BYTES)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-impl Default for ApiKey {
-    fn default() -> Self {
-        let key = (0..16).map(|_| rand::random::<u8>()).collect();
-        Self(key)
+// keeps the secret out of logs
+impl std::fmt::Debug for RawApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RawApiKey({}.***)", self.prefix())
     }
 }
 
-impl FromStr for ApiKey {
+impl FromStr for RawApiKey {
     type Err = ApiKeyError;
+
     fn from_str(key: &str) -> Result<Self, Self::Err> {
-        general_purpose::STANDARD
-            .decode(key)
-            .map(ApiKey)
-            .map_err(|e| Self::Err::DecodeError(e.to_string()))
+        let key = Self(key.trim().to_owned());
+        if key.legacy_bytes().is_some() {
+            return Ok(key);
+        }
+        match key.0.split_once('.') {
+            Some((prefix, secret))
+                if prefix.len() == PREFIX_BYTES * 2
+                    && prefix.bytes().all(|b| b.is_ascii_hexdigit())
+                    && general_purpose::URL_SAFE_NO_PAD
+                        .decode(secret)
+                        .is_ok_and(|secret| secret.len() == SECRET_BYTES) =>
+            {
+                Ok(key)
+            }
+            _ => Err(ApiKeyError::DecodeError(
+                "expected <prefix>.<secret>".to_owned(),
+            )),
+        }
     }
 }
 
-#[derive(Responder, Debug, thiserror::Error)]
+#[derive(Responder, Debug, Clone, thiserror::Error)]
 pub enum ApiError {
     #[error("not found")]
     #[response(status = 404, content_type = "json")]
@@ -72,6 +238,22 @@ pub enum ApiError {
     #[error("key error")]
     #[response(status = 400, content_type = "json")]
     KeyError(Json<ApiKeyError>),
+
+    #[error("forbidden")]
+    #[response(status = 403, content_type = "json")]
+    Forbidden(Json<String>),
+
+    #[error("conflict")]
+    #[response(status = 409, content_type = "json")]
+    Conflict(Json<String>),
+
+    #[error("invalid request")]
+    #[response(status = 400, content_type = "json")]
+    Invalid(Json<String>),
+
+    #[error("policy violation")]
+    #[response(status = 400, content_type = "json")]
+    Policy(Json<String>),
 }
 
 impl From<ServiceError> for ApiError {
@@ -81,6 +263,9 @@ impl From<ServiceError> for ApiError {
             ServiceError::NotFound => Self::NotFound(Json("entity not found".to_owned())),
             ServiceError::Data(_) => Self::Server(Json("a server error occurred".to_owned())),
             ServiceError::PermissionError(msg) => Self::User(Json(msg)),
+            ServiceError::Forbidden(msg) => Self::Forbidden(Json(msg)),
+            ServiceError::Conflict(msg) => Self::Conflict(Json(msg)),
+            ServiceError::Retention(e) => Self::Policy(Json(e.to_string())),
         }
     }
 }
@@ -89,97 +274,406 @@ impl From<ServiceError> for ApiError {
 impl<'r> FromRequest<'r> for ApiKey {
     type Error = ApiError;
 
+    /// verifies the key once per request, no matter how many guards ask for it
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        fn server_error() -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((
-                Status::InternalServerError,
-                ApiError::Server(Json("server error".to_string())),
-            ))
-        }
-
-        fn key_error(e: ApiKeyError) -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((Status::BadRequest, ApiError::KeyError(Json(e))))
-        }
-
-        match req.headers().get_one(API_KEY_HEADER) {
-            None => key_error(ApiKeyError::NotFound("API key not found".to_string())),
-            Some(key) => {
+        let outcome: &Result<ApiKey, (Status, ApiError)> = req
+            .local_cache_async(async {
+                let key = match req.headers().get_one(API_KEY_HEADER) {
+                    Some(key) => key,
+                    None => {
+                        return Err(key_error(ApiKeyError::NotFound(
+                            "API key not found".to_string(),
+                        )))
+                    }
+                };
+                let raw_key = RawApiKey::from_str(key).map_err(key_error)?;
                 let db = match req.guard::<&State<AppDatabase>>().await {
                     Outcome::Success(db) => db,
-                    _ => return server_error(),
+                    _ => return Err(server_error()),
                 };
 
-                let api_key = match ApiKey::from_str(key) {
-                    Ok(key) => key,
-                    Err(e) => return key_error(e),
-                };
-
-                match action::api_key_is_valid(api_key.clone(), db.get_pool()).await {
-                    Ok(valid) if valid => Outcome::Success(api_key),
-                    Ok(valid) if !valid => {
-                        key_error(ApiKeyError::NotFound("API key not found".to_owned()))
+                match action::authenticate_api_key(&raw_key, db.get_pool()).await {
+                    Ok(api_key) => Ok(api_key),
+                    Err(ServiceError::NotFound) => Err(key_error(ApiKeyError::NotFound(
+                        "API key not found".to_owned(),
+                    ))),
+                    Err(ServiceError::PermissionError(msg)) => {
+                        Err((Status::Unauthorized, ApiError::User(Json(msg))))
                     }
-                    _ => server_error(),
+                    Err(_) => Err(server_error()),
                 }
+            })
+            .await;
+
+        match outcome {
+            Ok(api_key) => Outcome::Success(api_key.clone()),
+            Err((status, e)) => Outcome::Error((*status, e.clone())),
+        }
+    }
+}
+
+fn server_error() -> (Status, ApiError) {
+    (
+        Status::InternalServerError,
+        ApiError::Server(Json("server error".to_string())),
+    )
+}
+
+fn key_error(e: ApiKeyError) -> (Status, ApiError) {
+    (Status::BadRequest, ApiError::KeyError(Json(e)))
+}
+
+/// Marker types naming the scope a route needs, see `Scoped`.
+pub mod scope {
+    use super::Scope;
+
+    pub trait Required: Send + Sync + 'static {
+        const SCOPE: Scope;
+    }
+
+    pub struct ClipRead;
+    pub struct ClipWrite;
+    pub struct ClipDelete;
+    pub struct Admin;
+
+    impl Required for ClipRead {
+        const SCOPE: Scope = Scope::ClipRead;
+    }
+
+    impl Required for ClipWrite {
+        const SCOPE: Scope = Scope::ClipWrite;
+    }
+
+    impl Required for ClipDelete {
+        const SCOPE: Scope = Scope::ClipDelete;
+    }
+
+    impl Required for Admin {
+        const SCOPE: Scope = Scope::Admin;
+    }
+}
+
+/// An API key holding the scope a route declares as `S`, e.g. `Scoped<scope::ClipWrite>`.
+pub struct Scoped<S: scope::Required>(ApiKey, std::marker::PhantomData<S>);
+
+impl<S: scope::Required> Scoped<S> {
+    pub fn into_inner(self) -> ApiKey {
+        self.0
+    }
+}
+
+impl<S: scope::Required> std::ops::Deref for Scoped<S> {
+    type Target = ApiKey;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, S: scope::Required> FromRequest<'r> for Scoped<S> {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.guard::<ApiKey>().await {
+            Outcome::Success(api_key) if api_key.has_scope(S::SCOPE) => {
+                Outcome::Success(Scoped(api_key, std::marker::PhantomData))
             }
+            Outcome::Success(_) => Outcome::Error((
+                Status::Forbidden,
+                ApiError::Forbidden(Json(format!("the API key lacks the '{}' scope", S::SCOPE))),
+            )),
+            Outcome::Error(e) => Outcome::Error(e),
+            Outcome::Forward(status) => Outcome::Forward(status),
         }
     }
 }
 
-#[rocket::get("/key")]
-pub async fn new_api_key(database: &State<AppDatabase>) -> Result<Json<&str>, ApiError> {
-    let api_key = action::generate_api_key(database.get_pool()).await?;
-    println!("Api key: {}", api_key.to_base64());
-    Ok(Json("Api key generated. See logs for details."))
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ask::Authorization {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(token) = req.headers().get_one(MANAGEMENT_TOKEN_HEADER) {
+            return match ManagementToken::from_str(token) {
+                Ok(token) => Outcome::Success(ask::Authorization::Token(token)),
+                Err(e) => Outcome::Error((Status::BadRequest, ServiceError::from(e).into())),
+            };
+        }
+
+        match req.guard::<ApiKey>().await {
+            Outcome::Success(api_key) if api_key.has_scope(Scope::Admin) => {
+                Outcome::Success(ask::Authorization::Admin)
+            }
+            Outcome::Success(_) => Outcome::Error((
+                Status::Unauthorized,
+                ApiError::User(Json("management token required".to_owned())),
+            )),
+            Outcome::Error(e) => Outcome::Error(e),
+            Outcome::Forward(status) => Outcome::Forward(status),
+        }
+    }
 }
 
 #[rocket::get("/<short_code>")]
 pub async fn get_clip(
-    short_code: &str,
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    cookies: &CookieJar<'_>,
+    views: &State<Views>,
+    api_key: Scoped<scope::ClipRead>,
+    conditions: Conditions,
+) -> Result<Conditional<Json<ClipView>>, ApiError> {
+    let req = || service::ask::GetClip {
+        short_code: short_code.clone(),
+        password: password_from_cookie(cookies),
+        caller: Some(api_key.clone()),
+    };
+
+    let pool = database.get_pool();
+    let json = Representation::Json;
+    if let Some(validators) = conditional::not_modified(&conditions, json, req(), pool).await {
+        return Ok(Conditional::NotModified(validators));
+    }
+    let clip = action::get_clip(req(), pool).await?;
+    views.view_clip(&clip);
+    let validators = Validators::new(&clip, json);
+    Ok(Conditional::Modified(Json(clip.into()), validators))
+}
+
+#[rocket::get("/<short_code>/attachment")]
+pub async fn get_attachment(
+    short_code: ShortCode,
     database: &State<AppDatabase>,
+    storage: &State<BlobStore>,
     cookies: &CookieJar<'_>,
     views: &State<Views>,
-    _api_key: ApiKey,
-) -> Result<Json<crate::Clip>, ApiError> {
-    use crate::domain::clip::field::Password;
+    api_key: Scoped<scope::ClipRead>,
+) -> Result<Download, ApiError> {
+    let req = service::ask::GetClip {
+        short_code,
+        password: password_from_cookie(cookies),
+        caller: Some(api_key.into_inner()),
+    };
+
+    let (clip, attachment, data) =
+        action::get_attachment(req, storage, database.get_pool()).await?;
+    views.view_clip(&clip);
+    Ok(Download::new(&attachment, data))
+}
 
+#[rocket::get("/<short_code>/revisions")]
+pub async fn get_revisions(
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    cookies: &CookieJar<'_>,
+    api_key: Scoped<scope::ClipRead>,
+) -> Result<Json<Vec<RevisionView>>, ApiError> {
+    let req = service::ask::GetClip {
+        short_code,
+        password: password_from_cookie(cookies),
+        caller: Some(api_key.into_inner()),
+    };
+
+    let (_, revisions) = action::get_revisions(req, database.get_pool()).await?;
+    Ok(Json(
+        revisions.into_iter().map(RevisionView::from).collect(),
+    ))
+}
+
+#[rocket::get("/<short_code>/revisions/<revision>")]
+pub async fn get_revision(
+    short_code: ShortCode,
+    revision: u64,
+    database: &State<AppDatabase>,
+    cookies: &CookieJar<'_>,
+    api_key: Scoped<scope::ClipRead>,
+) -> Result<Json<RevisionView>, ApiError> {
     let req = service::ask::GetClip {
-        short_code: short_code.into(),
-        password: cookies
-            .get(PASSWORD_COOKIE)
-            .map(|cookie| cookie.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+        short_code,
+        password: password_from_cookie(cookies),
+        caller: Some(api_key.into_inner()),
     };
 
-    let clip = action::get_clip(req, database.get_pool()).await?;
-    views.view(short_code.into(), 1);
-    Ok(Json(clip))
+    let revision = action::get_revision(req, revision, database.get_pool()).await?;
+    Ok(Json(revision.into()))
+}
+
+#[rocket::get("/?<cursor>&<limit>&<sort>&<status>&<scope>")]
+pub async fn list_clips(
+    cursor: Option<&str>,
+    limit: Option<u32>,
+    sort: Option<ask::ClipSort>,
+    status: Option<ask::ExpiryStatus>,
+    scope: Option<ask::ListScope>,
+    database: &State<AppDatabase>,
+    api_key: Scoped<scope::ClipRead>,
+) -> Result<Json<ClipPage>, ApiError> {
+    // an unparseable cursor would otherwise silently restart the listing
+    let cursor = cursor
+        .map(ask::Cursor::from_str)
+        .transpose()
+        .map_err(|e| ApiError::Invalid(Json(e.to_string())))?;
+    let req = ask::ListClips {
+        cursor,
+        limit,
+        sort: sort.unwrap_or_default(),
+        status: status.unwrap_or_default(),
+        scope: scope.unwrap_or_default(),
+    };
+
+    let page = action::list_clips(&api_key, req, database.get_pool()).await?;
+    Ok(Json(page.into()))
+}
+
+#[rocket::get("/search?<q>&<limit>")]
+pub async fn search_clips(
+    q: &str,
+    limit: Option<u32>,
+    database: &State<AppDatabase>,
+    api_key: Scoped<scope::ClipRead>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let req = ask::SearchClips {
+        query: q.to_owned(),
+        limit,
+    };
+
+    let hits = action::search_clips(&api_key, req, database.get_pool()).await?;
+    Ok(Json(hits.into_iter().map(SearchResult::from).collect()))
 }
 
 #[rocket::post("/", data = "<req>")]
 pub async fn new_clip(
-    req: Json<service::ask::NewClip>,
+    req: Result<Json<service::ask::NewClip>, json::Error<'_>>,
     database: &State<AppDatabase>,
-    _api_key: ApiKey,
-) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::new_clip(req.into_inner(), database.get_pool()).await?;
-    Ok(Json(clip))
+    retention: &State<RetentionPolicy>,
+    short_codes: &State<ShortCodeGenerator>,
+    api_key: Scoped<scope::ClipWrite>,
+) -> Result<Json<ClipView>, ApiError> {
+    // a field failing its own validation is as much the client's fault as malformed JSON
+    let req = req
+        .map_err(|e| ApiError::Invalid(Json(e.to_string())))?
+        .into_inner();
+    let owner = Some(&*api_key);
+    let pool = database.get_pool();
+    let (clip, token) = action::new_clip(req, owner, retention, short_codes, pool).await?;
+    Ok(Json(ClipView::from(clip).with_management_token(token)))
+}
+
+/// creates a clip from the `file` field of a multipart form, the other fields match `new_clip`
+#[rocket::post("/upload", data = "<form>")]
+pub async fn upload_clip(
+    form: Form<form::Upload<'_>>,
+    database: &State<AppDatabase>,
+    retention: &State<RetentionPolicy>,
+    short_codes: &State<ShortCodeGenerator>,
+    storage: &State<BlobStore>,
+    api_key: Scoped<scope::ClipWrite>,
+) -> Result<Json<ClipView>, ApiError> {
+    let req = form.into_inner().into_ask().await.map_err(|e| {
+        eprintln!("failed to read upload: {}", e);
+        ApiError::Server(Json("a server error occurred".to_owned()))
+    })?;
+    let owner = Some(&*api_key);
+    let pool = database.get_pool();
+    let (clip, token) =
+        action::upload_clip(req, owner, retention, short_codes, storage, pool).await?;
+    Ok(Json(ClipView::from(clip).with_management_token(token)))
 }
 
 #[rocket::put("/", data = "<req>")]
 pub async fn update_clip(
     req: Json<service::ask::UpdateClip>,
     database: &State<AppDatabase>,
-    _api_key: ApiKey,
-) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::update_clip(req.into_inner(), database.get_pool()).await?;
-    Ok(Json(clip))
+    retention: &State<RetentionPolicy>,
+    _api_key: Scoped<scope::ClipWrite>,
+    auth: ask::Authorization,
+) -> Result<Json<ClipView>, ApiError> {
+    let clip = action::update_clip(req.into_inner(), auth, retention, database.get_pool()).await?;
+    Ok(Json(clip.into()))
+}
+
+#[rocket::patch("/<short_code>", data = "<req>")]
+pub async fn patch_clip(
+    short_code: ShortCode,
+    req: Json<service::ask::PatchClip>,
+    database: &State<AppDatabase>,
+    retention: &State<RetentionPolicy>,
+    _api_key: Scoped<scope::ClipWrite>,
+    auth: ask::Authorization,
+) -> Result<Json<ClipView>, ApiError> {
+    let req = req.into_inner();
+    let clip = action::patch_clip(short_code, req, auth, retention, database.get_pool()).await?;
+    Ok(Json(clip.into()))
+}
+
+#[rocket::delete("/<short_code>")]
+pub async fn delete_clip(
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    storage: &State<BlobStore>,
+    _api_key: Scoped<scope::ClipDelete>,
+    auth: ask::Authorization,
+) -> Result<Json<&'static str>, ApiError> {
+    match action::delete_clip(short_code, auth, storage, database.get_pool()).await? {
+        DeletionStatus::Deleted => Ok(Json("clip deleted")),
+        DeletionStatus::NotFound => Err(ServiceError::NotFound.into()),
+    }
+}
+
+/// The secret of a new key, kept out of caches since it is never shown again.
+#[derive(Responder)]
+#[response(status = 201, content_type = "json")]
+pub struct IssuedKey {
+    key: Json<IssuedApiKey>,
+    cache_control: rocket::http::Header<'static>,
+}
+
+#[rocket::post("/", data = "<req>")]
+pub async fn issue_api_key(
+    req: Json<ask::NewApiKey>,
+    database: &State<AppDatabase>,
+    _admin: Scoped<scope::Admin>,
+) -> Result<IssuedKey, ApiError> {
+    let req = req.into_inner();
+    let raw_key = action::generate_api_key(req.clone(), database.get_pool()).await?;
+    Ok(IssuedKey {
+        key: Json((raw_key, req).into()),
+        cache_control: rocket::http::Header::new("Cache-Control", "no-store"),
+    })
+}
+
+#[rocket::delete("/<prefix>")]
+pub async fn revoke_api_key(
+    prefix: &str,
+    database: &State<AppDatabase>,
+    _admin: Scoped<scope::Admin>,
+) -> Result<Json<&'static str>, ApiError> {
+    match action::revoke_api_key(prefix, database.get_pool()).await? {
+        RevocationStatus::Revoked => Ok(Json("API key revoked")),
+        RevocationStatus::NotFound => Err(ServiceError::NotFound.into()),
+    }
 }
 
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![get_clip, new_clip, update_clip, new_api_key]
+    rocket::routes![
+        get_clip,
+        get_attachment,
+        new_clip,
+        upload_clip,
+        update_clip,
+        patch_clip,
+        delete_clip,
+        list_clips,
+        search_clips,
+        get_revisions,
+        get_revision
+    ]
+}
+
+/// key management, mounted apart from the clip routes
+pub fn key_routes() -> Vec<rocket::Route> {
+    rocket::routes![issue_api_key, revoke_api_key]
 }
 
 pub mod catcher {
@@ -189,13 +683,14 @@ pub mod catcher {
 
     #[catch(default)]
     fn default(req: &Request) -> Json<&'static str> {
-        eprintln!("general error: {:?}", req);
+        // only the method and uri, the headers carry API keys
+        eprintln!("general error: {}", req);
         Json("something went wrong...")
     }
 
     #[catch(500)]
     fn internal_error(req: &Request) -> Json<&'static str> {
-        eprintln!("internal error: {:?}", req);
+        eprintln!("internal error: {}", req);
         Json("internal server error")
     }
 
@@ -224,3 +719,871 @@ pub mod catcher {
         ]
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::AppDatabase;
+    use crate::test::async_runtime;
+    use crate::web::test::{client, config, runtime};
+    use crate::RocketConfig;
+    use rocket::http::{Cookie, Header, Status};
+    use rocket::local::blocking::Client;
+
+    use super::RawApiKey;
+
+    /// the header authenticating a request with `key`
+    fn auth(key: &RawApiKey) -> Header<'static> {
+        Header::new(super::API_KEY_HEADER, key.as_str().to_owned())
+    }
+
+    /// a client along with a key issued with the default scopes
+    fn client_with_key(config: RocketConfig) -> (Client, RawApiKey) {
+        let client = Client::tracked(crate::rocket(config)).unwrap();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let api_key = runtime()
+            .block_on(crate::service::action::generate_api_key(
+                Default::default(),
+                db.get_pool(),
+            ))
+            .unwrap();
+        (client, api_key)
+    }
+
+    #[test]
+    fn clip_response_hides_password() {
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
+        use crate::service;
+
+        let rt = async_runtime();
+
+        let (client, api_key) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = service::ask::NewClip {
+            content: Content::new("content").unwrap(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::new("123".to_owned()).unwrap(),
+            max_views: MaxViews::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+            title: Title::default(),
+        };
+
+        let (clip, _) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let response = client
+            .get(format!("/api/clip/{}", clip.short_code.as_str()))
+            .header(auth(&api_key))
+            .cookie(Cookie::new("password", "123"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["has_password"], true);
+        assert_eq!(body["content"], "content");
+        assert!(body.get("password").is_none());
+    }
+
+    #[test]
+    fn update_requires_management_token() {
+        use rocket::http::ContentType;
+
+        let (client, api_key) = client_with_key(config());
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(r#"{"content":"content","title":null,"exprires_at":null,"password":null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert!(body["management_token"].is_string());
+
+        let update = format!(
+            r#"{{"content":"updated","title":null,"exprires_at":null,"password":null,"short_code":{}}}"#,
+            body["short_code"]
+        );
+
+        // Reject update when no token is provided
+        let response = client
+            .put("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(update.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // Reject update when the token is incorrect
+        let response = client
+            .put("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .header(Header::new(super::MANAGEMENT_TOKEN_HEADER, "incorrect"))
+            .body(update.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // Accept update with the token returned on creation, keeping the short code
+        let token = body["management_token"].as_str().unwrap().to_owned();
+        let response = client
+            .put("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .header(Header::new(super::MANAGEMENT_TOKEN_HEADER, token))
+            .body(update)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let updated: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(updated["content"], "updated");
+        assert_eq!(updated["short_code"], body["short_code"]);
+    }
+
+    #[test]
+    fn patches_clip() {
+        use rocket::http::ContentType;
+
+        let (client, api_key) = client_with_key(config());
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(r#"{"content":"content","title":"title","exprires_at":null,"password":null}"#)
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        let short_code = body["short_code"].as_str().unwrap();
+        let token = body["management_token"].as_str().unwrap();
+
+        let response = client
+            .patch(format!("/api/clip/{}", short_code))
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.to_owned(),
+            ))
+            .body(r#"{"content":"updated"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let patched: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(patched["short_code"], short_code);
+        assert_eq!(patched["content"], "updated");
+        assert_eq!(patched["title"], "title");
+
+        let response = client
+            .patch("/api/clip/missing")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.to_owned(),
+            ))
+            .body(r#"{"content":"updated"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn deletes_clip() {
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
+        use crate::service;
+
+        let rt = async_runtime();
+
+        let (client, api_key) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = service::ask::NewClip {
+            content: Content::new("content").unwrap(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::default(),
+            max_views: MaxViews::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+            title: Title::default(),
+        };
+
+        let (clip, token) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        let uri = format!("/api/clip/{}", clip.short_code.as_str());
+
+        // Keep clip when the token is incorrect
+        let response = client
+            .delete(uri.as_str())
+            .header(auth(&api_key))
+            .header(Header::new(super::MANAGEMENT_TOKEN_HEADER, "incorrect"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .delete(uri.as_str())
+            .header(auth(&api_key))
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.as_str().to_owned(),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // Report nothing removed once the clip is gone
+        let response = client
+            .delete(uri.as_str())
+            .header(auth(&api_key))
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.into_inner(),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn rejects_zero_max_views() {
+        use rocket::http::ContentType;
+
+        let (client, api_key) = client_with_key(config());
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(r#"{"content":"content","title":null,"exprires_at":null,"password":null,"max_views":0}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let body: String = response.into_json().unwrap();
+        assert!(body.contains("at least one view"));
+    }
+
+    #[test]
+    fn applies_retention_policy() {
+        use crate::domain::retention::RetentionPolicy;
+        use crate::web::test::config;
+        use chrono::Duration;
+        use rocket::http::ContentType;
+
+        let mut config = config();
+        config.retention =
+            RetentionPolicy::new(Some(Duration::hours(1)), Some(Duration::days(1))).unwrap();
+        let (client, api_key) = client_with_key(config);
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(r#"{"content":"content","title":null,"exprires_at":"2d","password":null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let body: String = response.into_json().unwrap();
+        assert_eq!(body, "clips may not be kept longer than 1d");
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(r#"{"content":"content","title":null,"exprires_at":null,"password":null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert!(!body["expires_at"].is_null());
+        let short_code = body["short_code"].as_str().unwrap();
+        let token = body["management_token"].as_str().unwrap();
+
+        // clearing the expiry falls back to the default instead of keeping the clip forever
+        let response = client
+            .patch(format!("/api/clip/{}", short_code))
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.to_owned(),
+            ))
+            .body(r#"{"expires_at":null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert!(!body["expires_at"].is_null());
+    }
+
+    #[test]
+    fn rejects_taken_vanity_code() {
+        use rocket::http::ContentType;
+
+        let (client, api_key) = client_with_key(config());
+
+        let new_clip = || {
+            client
+                .post("/api/clip")
+                .header(ContentType::JSON)
+                .header(auth(&api_key))
+                .body(
+                    r#"{"content":"content","title":null,"exprires_at":null,"password":null,
+                        "short_code":"deploy-notes"}"#,
+                )
+                .dispatch()
+        };
+
+        let response = new_clip();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["short_code"], "deploy-notes");
+
+        let response = new_clip();
+        assert_eq!(response.status(), Status::Conflict);
+        let body: String = response.into_json().unwrap();
+        assert_eq!(body, "short code 'deploy-notes' is already taken");
+    }
+
+    #[test]
+    fn lists_revisions() {
+        use rocket::http::ContentType;
+
+        let (client, api_key) = client_with_key(config());
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .body(r#"{"content":"first","title":null,"exprires_at":null,"password":null}"#)
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        let short_code = body["short_code"].as_str().unwrap();
+        let token = body["management_token"].as_str().unwrap();
+
+        let response = client
+            .patch(format!("/api/clip/{}", short_code))
+            .header(ContentType::JSON)
+            .header(auth(&api_key))
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.to_owned(),
+            ))
+            .body(r#"{"content":"second"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(format!("/api/clip/{}/revisions", short_code))
+            .header(auth(&api_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let revisions: Vec<serde_json::Value> = response.into_json().unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0]["revision"], 1);
+        assert_eq!(revisions[0]["content"], "first");
+
+        let response = client
+            .get(format!("/api/clip/{}/revisions/1", short_code))
+            .header(auth(&api_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let revision: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(revision["content"], "first");
+
+        let response = client
+            .get(format!("/api/clip/{}/revisions/2", short_code))
+            .header(auth(&api_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn lists_own_clips_in_pages() {
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let (client, api_key) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let other_key = rt
+            .block_on(async move {
+                service::action::generate_api_key(Default::default(), db.get_pool()).await
+            })
+            .unwrap();
+
+        let new_clip = |api_key: &super::RawApiKey, content: &str| {
+            let response = client
+                .post("/api/clip")
+                .header(ContentType::JSON)
+                .header(auth(api_key))
+                .body(format!(
+                    r#"{{"content":"{}","title":null,"exprires_at":null,"password":null}}"#,
+                    content
+                ))
+                .dispatch();
+            let body: serde_json::Value = response.into_json().unwrap();
+            body["short_code"].as_str().unwrap().to_owned()
+        };
+        let mut created: Vec<String> = (0..5).map(|i| new_clip(&api_key, &i.to_string())).collect();
+        new_clip(&other_key, "not mine");
+
+        let list = |query: &str| {
+            let response = client
+                .get(format!("/api/clip?{}", query))
+                .header(auth(&api_key))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<crate::web::ClipPage>().unwrap()
+        };
+
+        let mut listed = vec![];
+        let mut page = list("limit=2");
+        loop {
+            assert!(page.clips.len() <= 2);
+            listed.extend(page.clips.iter().map(|clip| clip.short_code.clone()));
+            match page.next_cursor {
+                Some(cursor) => page = list(&format!("limit=2&cursor={}", cursor)),
+                None => break,
+            }
+        }
+        listed.sort();
+        created.sort();
+        assert_eq!(listed, created);
+
+        let page = list("sort=views&status=expired");
+        assert!(page.clips.is_empty());
+        let page = list("sort=views&status=unexpired&limit=100");
+        assert_eq!(page.clips.len(), 5);
+        assert!(page.next_cursor.is_none());
+
+        let response = client
+            .get("/api/clip?cursor=garbage")
+            .header(auth(&api_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn searches_own_clips() {
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let (client, api_key) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let other_key = rt
+            .block_on(async move {
+                service::action::generate_api_key(Default::default(), db.get_pool()).await
+            })
+            .unwrap();
+
+        let new_clip = |api_key: &super::RawApiKey, body: serde_json::Value| {
+            let response = client
+                .post("/api/clip")
+                .header(ContentType::JSON)
+                .header(auth(api_key))
+                .body(body.to_string())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        };
+        let clip = |content: &str, title: Option<&str>, password: Option<&str>| {
+            serde_json::json!({
+                "content": content,
+                "title": title,
+                "exprires_at": null,
+                "password": password,
+            })
+        };
+        new_clip(&api_key, clip("restart <nginx> after deploy", None, None));
+        new_clip(&api_key, clip("unrelated", Some("nginx config"), None));
+        new_clip(&api_key, clip("nginx secret", None, Some("123")));
+        new_clip(&other_key, clip("nginx from someone else", None, None));
+
+        let search = |query: &str| {
+            let response = client
+                .get(format!("/api/clip/search?q={}", query))
+                .header(auth(&api_key))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response
+                .into_json::<Vec<crate::web::SearchResult>>()
+                .unwrap()
+        };
+
+        let results = search("nginx");
+        assert_eq!(results.len(), 2);
+        // title matches weigh more than content matches
+        assert_eq!(results[0].title.as_deref(), Some("nginx config"));
+        assert_eq!(
+            results[1].snippet,
+            "restart &lt;<mark>nginx</mark>&gt; after deploy"
+        );
+
+        assert!(search("secret").is_empty());
+        assert!(search("someone").is_empty());
+        assert!(search("%22unbalanced").is_empty());
+        assert!(search("").is_empty());
+    }
+
+    #[test]
+    fn enforces_visibility() {
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let (client, api_key) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let other_key = rt
+            .block_on(async move {
+                service::action::generate_api_key(Default::default(), db.get_pool()).await
+            })
+            .unwrap();
+
+        let new_clip = |content: &str, visibility: &str| {
+            let response = client
+                .post("/api/clip")
+                .header(ContentType::JSON)
+                .header(auth(&api_key))
+                .body(format!(
+                    r#"{{"content":"{}","title":null,"exprires_at":null,"password":null,
+                        "visibility":"{}"}}"#,
+                    content, visibility
+                ))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let body: serde_json::Value = response.into_json().unwrap();
+            assert_eq!(body["visibility"], visibility);
+            body["short_code"].as_str().unwrap().to_owned()
+        };
+        let private = new_clip("private words", "private");
+        let unlisted = new_clip("unlisted words", "unlisted");
+        let public = new_clip("public words", "public");
+
+        let get = |short_code: &str, api_key: &super::RawApiKey| {
+            client
+                .get(format!("/api/clip/{}", short_code))
+                .header(auth(api_key))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(get(&private, &api_key), Status::Ok);
+        assert_eq!(get(&private, &other_key), Status::Forbidden);
+        assert_eq!(get(&unlisted, &other_key), Status::Ok);
+        assert_eq!(get(&public, &other_key), Status::Ok);
+
+        let response = client.get(format!("/clip/{}", private)).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.get(format!("/clip/raw/{}", private)).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .get("/api/clip?scope=public")
+            .header(auth(&other_key))
+            .dispatch();
+        let page: crate::web::ClipPage = response.into_json().unwrap();
+        let listed: Vec<_> = page.clips.into_iter().map(|clip| clip.short_code).collect();
+        assert_eq!(listed, vec![public]);
+
+        let response = client
+            .get("/api/clip/search?q=words")
+            .header(auth(&other_key))
+            .dispatch();
+        let results: Vec<crate::web::SearchResult> = response.into_json().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].snippet, "public <mark>words</mark>");
+    }
+
+    #[test]
+    fn uploads_and_downloads_attachments() {
+        use crate::web::test::{config, multipart};
+        use crate::web::ClipView;
+        use rocket::data::ToByteUnit;
+
+        let mut config = config();
+        config.max_upload_size = 64.bytes();
+        let (client, api_key) = client_with_key(config);
+
+        let data = [0x1f, 0x8b, 0x08, 0x00, 0xff];
+        let (content_type, body) = multipart(
+            &[("title", "logs")],
+            ("../server log.gz", "application/gzip", &data),
+        );
+        let response = client
+            .post("/api/clip/upload")
+            .header(content_type)
+            .header(auth(&api_key))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let clip: ClipView = response.into_json().unwrap();
+        assert_eq!(clip.content, "server log.gz");
+        let attachment = clip.attachment.unwrap();
+        assert_eq!(attachment.filename(), "server log.gz");
+        assert_eq!(attachment.mime_type(), "application/gzip");
+        assert_eq!(attachment.size(), 5);
+
+        let response = client
+            .get(format!("/api/clip/{}/attachment", clip.short_code))
+            .header(auth(&api_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("application/gzip")
+        );
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"server log.gz\"; filename*=UTF-8''server%20log.gz")
+        );
+        assert_eq!(response.into_bytes().unwrap(), data);
+
+        let (content_type, body) =
+            multipart(&[], ("large.bin", "application/octet-stream", &[0; 65]));
+        let response = client
+            .post("/api/clip/upload")
+            .header(content_type)
+            .header(auth(&api_key))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+    }
+
+    #[test]
+    fn answers_conditional_requests() {
+        use crate::domain::clip::field::Content;
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let (client, api_key) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = service::ask::NewClip {
+            content: Content::new("content").unwrap(),
+            exprires_at: Default::default(),
+            password: Default::default(),
+            max_views: Default::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+            title: Default::default(),
+        };
+        let (clip, token) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        let uri = format!("/api/clip/{}", clip.short_code.as_str());
+        let api_key = auth(&api_key);
+
+        let response = client.get(uri.as_str()).header(api_key.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let etag = response.headers().get_one("ETag").unwrap().to_owned();
+        let last_modified = response
+            .headers()
+            .get_one("Last-Modified")
+            .unwrap()
+            .to_owned();
+
+        let response = client
+            .get(uri.as_str())
+            .header(api_key.clone())
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+        let response = client
+            .get(uri.as_str())
+            .header(api_key.clone())
+            .header(Header::new("If-Modified-Since", last_modified))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+
+        let response = client
+            .patch(uri.as_str())
+            .header(api_key.clone())
+            .header(Header::new(
+                super::MANAGEMENT_TOKEN_HEADER,
+                token.into_inner(),
+            ))
+            .header(ContentType::JSON)
+            .body(r#"{"content": "changed"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(uri.as_str())
+            .header(api_key)
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    }
+
+    #[test]
+    fn raw_api_keys_are_validated() {
+        use super::RawApiKey;
+        use std::str::FromStr;
+
+        let key = RawApiKey::generate();
+        let parsed = RawApiKey::from_str(key.as_str()).unwrap();
+        assert_eq!(parsed, key);
+        assert_eq!(parsed.prefix().len(), 12);
+        assert!(!format!("{:?}", parsed).contains(key.as_str()));
+
+        // keys issued before prefixes keep working
+        let legacy = RawApiKey::from_str("AAAAAAAAAAAAAAAAAAAAAA==").unwrap();
+        assert_eq!(legacy.prefix(), legacy.hash()[..12]);
+        assert_ne!(legacy.prefix(), "000000000000");
+        assert!(RawApiKey::from_str("").is_err());
+        assert!(RawApiKey::from_str("abc.def").is_err());
+        assert!(RawApiKey::from_str("zzzzzzzzzzzz.AAAA").is_err());
+    }
+
+    #[test]
+    fn routes_enforce_key_scopes() {
+        use super::Scope;
+        use crate::domain::clip::field::ExpiresAt;
+        use crate::service::{action, ask};
+        use crate::Time;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let (reader, expired) = rt
+            .block_on(async move {
+                let reader = ask::NewApiKey {
+                    name: "reader".to_owned(),
+                    scopes: vec![Scope::ClipRead],
+                    expires_at: Default::default(),
+                };
+                let expired = ask::NewApiKey {
+                    expires_at: ExpiresAt::new(Time::from_naive_utc(
+                        chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1),
+                    )),
+                    ..Default::default()
+                };
+                Ok::<_, crate::ServiceError>((
+                    action::generate_api_key(reader, db.get_pool()).await?,
+                    action::generate_api_key(expired, db.get_pool()).await?,
+                ))
+            })
+            .unwrap();
+        let reader = auth(&reader);
+        let expired = auth(&expired);
+
+        let response = client.get("/api/clip").header(reader.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(reader.clone())
+            .body(r#"{"content":"content"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.delete("/api/clip/abc").header(reader).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.get("/api/clip").header(expired).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn admins_issue_and_revoke_keys() {
+        use super::Scope;
+        use crate::service::{action, ask};
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let (client, user) = client_with_key(config());
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let admin = ask::NewApiKey {
+            scopes: vec![Scope::Admin],
+            ..Default::default()
+        };
+        let admin = rt
+            .block_on(action::generate_api_key(admin, db.get_pool()))
+            .unwrap();
+        let admin = auth(&admin);
+        let user = auth(&user);
+        let body = r#"{"name": "ci", "scopes": ["clip:read"]}"#;
+
+        let response = client
+            .post("/api/keys")
+            .header(ContentType::JSON)
+            .header(user)
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .post("/api/keys")
+            .header(ContentType::JSON)
+            .header(admin.clone())
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("no-store")
+        );
+        let issued: crate::web::IssuedApiKey = response.into_json().unwrap();
+        assert_eq!(issued.name, "ci");
+        assert_eq!(issued.scopes, vec![Scope::ClipRead]);
+        assert!(issued.key.starts_with(&issued.prefix));
+        let issued_key = auth(&issued.key.parse().unwrap());
+
+        let response = client
+            .get("/api/clip")
+            .header(issued_key.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let uri = format!("/api/keys/{}", issued.prefix);
+        let response = client.delete(uri.as_str()).header(admin.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.delete(uri.as_str()).header(admin).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get("/api/clip").header(issued_key).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::data::DatabasePool;
+use crate::service::{action, ask};
+use crate::Clip;
+use chrono::{DateTime, Utc};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use sha2::{Digest, Sha256};
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The form a clip is sent in, each has entity tags of its own since their bytes differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    Html,
+    Raw,
+    RawDownload,
+    Json,
+}
+
+impl Representation {
+    /// the raw text, saved as a file when `download` is set
+    pub fn raw(download: bool) -> Self {
+        match download {
+            true => Self::RawDownload,
+            false => Self::Raw,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Raw => "raw",
+            Self::RawDownload => "raw+download",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Identifies the current version of a clip, sent as `ETag` and `Last-Modified`.
+#[derive(Debug, Clone)]
+pub struct Validators {
+    etag: String,
+    last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// the entity tag changes with the content and revision of the clip, and with any update
+    /// to its settings
+    pub fn new(clip: &Clip, representation: Representation) -> Self {
+        let last_modified = clip.last_modified().into_inner();
+        let mut hasher = Sha256::new();
+        hasher.update(representation.as_str());
+        hasher.update(clip.short_code.as_str());
+        hasher.update(clip.revision.to_be_bytes());
+        hasher.update(last_modified.timestamp().to_be_bytes());
+        hasher.update(clip.content.as_str());
+
+        Self {
+            etag: format!("\"{:x}\"", hasher.finalize()),
+            last_modified,
+        }
+    }
+
+    fn apply(self, response: &mut Response<'_>) {
+        response.set_header(Header::new("ETag", self.etag));
+        response.set_header(Header::new(
+            "Last-Modified",
+            self.last_modified.format(HTTP_DATE).to_string(),
+        ));
+        // clips may be private or behind a password, and change without notice
+        response.set_header(Header::new("Cache-Control", "private, no-cache"));
+    }
+}
+
+/// The `If-None-Match` and `If-Modified-Since` headers of a request.
+#[derive(Debug, Default)]
+pub struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl Conditions {
+    pub fn is_empty(&self) -> bool {
+        self.if_none_match.is_none() && self.if_modified_since.is_none()
+    }
+
+    /// whether the copy the client has is current, `If-Modified-Since` is only considered
+    /// without `If-None-Match`
+    pub fn is_fresh(&self, validators: &Validators) -> bool {
+        match (&self.if_none_match, self.if_modified_since) {
+            (Some(etags), _) => etags
+                .split(',')
+                .map(|etag| etag.trim().trim_start_matches("W/"))
+                .any(|etag| etag == "*" || etag == validators.etag),
+            (None, Some(since)) => validators.last_modified.timestamp() <= since.timestamp(),
+            (None, None) => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Conditions {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req.headers();
+        Outcome::Success(Self {
+            if_none_match: headers.get_one("If-None-Match").map(str::to_owned),
+            if_modified_since: headers
+                .get_one("If-Modified-Since")
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+        })
+    }
+}
+
+/// A response showing a clip, along with its validators, or a bare `304 Not Modified`.
+pub enum Conditional<R> {
+    Modified(R, Validators),
+    NotModified(Validators),
+    /// responses that do not show the clip, like password prompts
+    Uncached(R),
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Conditional<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Self::Modified(inner, validators) => {
+                let mut response = inner.respond_to(req)?;
+                validators.apply(&mut response);
+                Ok(response)
+            }
+            Self::NotModified(validators) => {
+                let mut response = Response::build().status(Status::NotModified).finalize();
+                validators.apply(&mut response);
+                Ok(response)
+            }
+            Self::Uncached(inner) => inner.respond_to(req),
+        }
+    }
+}
+
+/// the validators of the clip when the copy the client has is current
+///
+/// Access is checked as for reading the clip, but no view is counted so a client polling
+/// for changes does not use up the views of a clip or burn it.
+pub async fn not_modified(
+    conditions: &Conditions,
+    representation: Representation,
+    req: ask::GetClip,
+    pool: &DatabasePool,
+) -> Option<Validators> {
+    if conditions.is_empty() {
+        return None;
+    }
+    let clip = action::peek_clip(req, pool).await.ok()?;
+    let validators = Validators::new(&clip, representation);
+    conditions.is_fresh(&validators).then_some(validators)
+}
@@ -1,3 +1,4 @@
+use crate::web::ClipView;
 use derive_more::Constructor;
 use serde::Serialize;
 
@@ -7,8 +8,23 @@ pub trait PageContext {
     fn parent(&self) -> &str;
 }
 
-#[derive(Debug, Serialize, Default)]
-pub struct Home {}
+#[derive(Debug, Serialize)]
+pub struct Home {
+    pub notice: Option<String>,
+    /// offered in addition to automatic detection
+    pub languages: Vec<&'static str>,
+}
+
+impl Default for Home {
+    fn default() -> Self {
+        use crate::domain::clip::field::LANGUAGES;
+
+        Self {
+            notice: None,
+            languages: LANGUAGES.iter().map(|(language, _)| *language).collect(),
+        }
+    }
+}
 
 impl PageContext for Home {
     fn title(&self) -> &str {
@@ -24,9 +40,35 @@ impl PageContext for Home {
     }
 }
 
-#[derive(Debug, Serialize, Constructor)]
+#[derive(Debug, Serialize)]
 pub struct ViewClip {
-    pub clip: crate::Clip,
+    pub clip: ClipView,
+    pub management_token: Option<String>,
+    /// absolute link to the clip, set when the page is not served from it
+    pub clip_url: Option<String>,
+    /// the content as highlighted HTML, missing for clips carrying a file
+    pub highlighted: Option<String>,
+}
+
+impl ViewClip {
+    pub fn new(clip: crate::Clip) -> Self {
+        use crate::domain::clip::field::Rendering;
+
+        let language = match clip.rendering {
+            Rendering::Plain => None,
+            _ => clip.language.as_deref(),
+        };
+        let highlighted = match clip.attachment {
+            Some(_) => None,
+            None => Some(super::highlight::highlight(clip.content.as_str(), language)),
+        };
+        Self {
+            clip: clip.into(),
+            management_token: None,
+            clip_url: None,
+            highlighted,
+        }
+    }
 }
 
 impl PageContext for ViewClip {
@@ -43,6 +85,47 @@ impl PageContext for ViewClip {
     }
 }
 
+/// A markdown clip rendered to HTML, its source can be toggled to without another request.
+#[derive(Debug, Serialize)]
+pub struct RenderedClip {
+    pub clip: ClipView,
+    pub management_token: Option<String>,
+    /// absolute link to the clip, set when the page is not served from it
+    pub clip_url: Option<String>,
+    /// sanitized HTML of the markdown
+    pub rendered: String,
+    /// the markdown source as highlighted HTML
+    pub highlighted: String,
+}
+
+impl RenderedClip {
+    pub fn new(clip: crate::Clip) -> Self {
+        let rendered = super::markdown::render(clip.content.as_str());
+        let highlighted = super::highlight::highlight(clip.content.as_str(), Some("markdown"));
+        Self {
+            clip: clip.into(),
+            management_token: None,
+            clip_url: None,
+            rendered,
+            highlighted,
+        }
+    }
+}
+
+impl PageContext for RenderedClip {
+    fn title(&self) -> &str {
+        "View Clip"
+    }
+
+    fn template_path(&self) -> &str {
+        "clip"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
 #[derive(Debug, Serialize, Constructor)]
 pub struct PasswordRequired {
     short_code: crate::ShortCode,
@@ -61,3 +144,116 @@ impl PageContext for PasswordRequired {
         "base"
     }
 }
+
+#[derive(Debug, Serialize)]
+pub struct HistoryVersion {
+    pub number: u64,
+    pub label: String,
+    pub from: bool,
+    pub to: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub change: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct History {
+    pub short_code: String,
+    pub title: Option<String>,
+    pub versions: Vec<HistoryVersion>,
+    pub diff: Vec<DiffLine>,
+}
+
+impl History {
+    /// Versions are numbered like revisions, the current content comes after the last revision.
+    ///
+    /// Compares the last revision to the current content unless `from` and `to` are given.
+    pub fn new(
+        clip: crate::Clip,
+        revisions: Vec<crate::domain::Revision>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Option<Self> {
+        use similar::{ChangeTag, TextDiff};
+
+        let current = revisions.len() as u64 + 1;
+        let from = from.unwrap_or(current.saturating_sub(1).max(1));
+        let to = to.unwrap_or(current);
+        if !(1..=current).contains(&from) || !(1..=current).contains(&to) {
+            return None;
+        }
+
+        let mut versions: Vec<(HistoryVersion, String)> = revisions
+            .into_iter()
+            .map(|revision| {
+                let label = format!(
+                    "Revision {} (replaced {})",
+                    revision.revision,
+                    revision
+                        .revised_at
+                        .into_inner()
+                        .format("%Y-%m-%d %H:%M UTC")
+                );
+                (revision.revision, label, revision.content.into_inner())
+            })
+            .chain(std::iter::once((
+                current,
+                "Current".to_owned(),
+                clip.content.into_inner(),
+            )))
+            .map(|(number, label, content)| {
+                let version = HistoryVersion {
+                    number,
+                    label,
+                    from: number == from,
+                    to: number == to,
+                };
+                (version, content)
+            })
+            .collect();
+        versions.reverse();
+
+        let content = |number: u64| {
+            versions
+                .iter()
+                .find(|(version, _)| version.number == number)
+                .map(|(_, content)| content.as_str())
+                .unwrap_or_default()
+        };
+        let diff = TextDiff::from_lines(content(from), content(to))
+            .iter_all_changes()
+            .map(|change| DiffLine {
+                change: match change.tag() {
+                    ChangeTag::Delete => "delete",
+                    ChangeTag::Insert => "insert",
+                    ChangeTag::Equal => "equal",
+                },
+                text: change.value().trim_end_matches(['\r', '\n']).to_owned(),
+            })
+            .collect();
+
+        Some(Self {
+            short_code: clip.short_code.into_inner(),
+            title: clip.title.into_inner(),
+            versions: versions.into_iter().map(|(version, _)| version).collect(),
+            diff,
+        })
+    }
+}
+
+impl PageContext for History {
+    fn title(&self) -> &str {
+        "Clip History"
+    }
+
+    fn template_path(&self) -> &str {
+        "history"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
@@ -0,0 +1,183 @@
+use crate::domain::clip::field::{Attachment, Language, ManagementToken, Rendering, Visibility};
+use crate::domain::{Revision, SearchHit};
+use crate::service::ask::{NewApiKey, Page};
+use crate::web::api::{RawApiKey, Scope};
+use crate::{Clip, Time};
+use serde::{Deserialize, Serialize};
+
+/// Public representation of a clip, safe to hand out to clients and templates.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClipView {
+    pub short_code: String,
+    /// the text of the clip, or the description of its attachment
+    pub content: String,
+    #[serde(default)]
+    pub attachment: Option<Attachment>,
+    #[serde(default)]
+    pub language: Language,
+    #[serde(default)]
+    pub rendering: Rendering,
+    pub title: Option<String>,
+    pub created_at: Time,
+    pub expires_at: Option<Time>,
+    pub views: u64,
+    pub max_views: Option<u64>,
+    pub has_password: bool,
+    pub burn_after_read: bool,
+    pub visibility: Visibility,
+
+    /// only present in the response to the request that created the clip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub management_token: Option<String>,
+}
+
+impl ClipView {
+    pub fn with_management_token(self, token: ManagementToken) -> Self {
+        Self {
+            management_token: Some(token.into_inner()),
+            ..self
+        }
+    }
+}
+
+impl From<Clip> for ClipView {
+    fn from(clip: Clip) -> Self {
+        Self {
+            has_password: clip.password.has_password(),
+            burn_after_read: clip.burn_after_read,
+            visibility: clip.visibility,
+            short_code: clip.short_code.into_inner(),
+            content: clip.content.into_inner(),
+            attachment: clip.attachment,
+            language: clip.language,
+            rendering: clip.rendering,
+            title: clip.title.into_inner(),
+            created_at: clip.created_at.into_inner(),
+            expires_at: clip.expires_at.into_inner(),
+            views: clip.views.into_inner(),
+            max_views: clip.max_views.into_inner(),
+            management_token: None,
+        }
+    }
+}
+
+/// A prior version of a clip.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RevisionView {
+    pub revision: u64,
+    pub content: String,
+    pub title: Option<String>,
+    pub expires_at: Option<Time>,
+    pub revised_at: Time,
+}
+
+impl From<Revision> for RevisionView {
+    fn from(revision: Revision) -> Self {
+        Self {
+            revision: revision.revision,
+            content: revision.content.into_inner(),
+            title: revision.title.into_inner(),
+            expires_at: revision.expires_at.into_inner(),
+            revised_at: revision.revised_at,
+        }
+    }
+}
+
+/// A clip in a listing, without its content.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClipSummary {
+    pub short_code: String,
+    #[serde(default)]
+    pub attachment: Option<Attachment>,
+    pub title: Option<String>,
+    pub created_at: Time,
+    pub expires_at: Option<Time>,
+    pub views: u64,
+    pub max_views: Option<u64>,
+    pub has_password: bool,
+    pub burn_after_read: bool,
+    pub visibility: Visibility,
+}
+
+impl From<Clip> for ClipSummary {
+    fn from(clip: Clip) -> Self {
+        Self {
+            has_password: clip.password.has_password(),
+            burn_after_read: clip.burn_after_read,
+            visibility: clip.visibility,
+            short_code: clip.short_code.into_inner(),
+            attachment: clip.attachment,
+            title: clip.title.into_inner(),
+            created_at: clip.created_at.into_inner(),
+            expires_at: clip.expires_at.into_inner(),
+            views: clip.views.into_inner(),
+            max_views: clip.max_views.into_inner(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClipPage {
+    pub clips: Vec<ClipSummary>,
+    /// passed as `cursor` to fetch the next page, missing on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl From<Page<Clip>> for ClipPage {
+    fn from(page: Page<Clip>) -> Self {
+        Self {
+            clips: page.items.into_iter().map(ClipSummary::from).collect(),
+            next_cursor: page.next_cursor.map(|cursor| cursor.to_string()),
+        }
+    }
+}
+
+/// A search match, `snippet` is HTML with matches wrapped in `<mark>`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub short_code: String,
+    pub title: Option<String>,
+    pub created_at: Time,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+impl From<SearchHit> for SearchResult {
+    fn from(hit: SearchHit) -> Self {
+        use crate::domain::clip::{SNIPPET_MATCH_END, SNIPPET_MATCH_START};
+
+        let snippet = handlebars::html_escape(&hit.snippet)
+            .replace(SNIPPET_MATCH_START, "<mark>")
+            .replace(SNIPPET_MATCH_END, "</mark>");
+
+        Self {
+            short_code: hit.short_code.into_inner(),
+            title: hit.title.into_inner(),
+            created_at: hit.created_at.into_inner(),
+            snippet,
+            rank: hit.rank,
+        }
+    }
+}
+
+/// A newly issued API key, the only time its secret is handed out.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IssuedApiKey {
+    pub key: String,
+    pub prefix: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<Time>,
+}
+
+impl From<(RawApiKey, NewApiKey)> for IssuedApiKey {
+    fn from((raw_key, req): (RawApiKey, NewApiKey)) -> Self {
+        Self {
+            prefix: raw_key.prefix(),
+            key: raw_key.as_str().to_owned(),
+            name: req.name,
+            scopes: req.scopes,
+            expires_at: req.expires_at.into_inner(),
+        }
+    }
+}
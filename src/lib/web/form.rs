@@ -1,16 +1,79 @@
 use crate::domain::clip::field;
-use rocket::form::FromForm;
+use crate::service::ask;
+use rocket::form::{self, FromForm};
+use rocket::fs::TempFile;
 use serde::Serialize;
 
 #[derive(Debug, Serialize, FromForm)]
-pub struct NewClip {
-    pub content: field::Content,
+pub struct NewClip<'r> {
+    /// missing for clips carrying a file
+    pub content: Option<field::Content>,
     pub title: field::Title,
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
+    pub max_views: field::MaxViews,
+    pub burn_after_read: bool,
+    pub short_code: field::VanityCode,
+    pub visibility: field::Visibility,
+    pub language: field::Language,
+    pub rendering: field::Rendering,
+    /// browsers send an empty file when none was picked, an oversized one is kept as an error
+    #[serde(skip)]
+    pub file: form::Result<'r, TempFile<'r>>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct Upload<'r> {
+    pub file: TempFile<'r>,
+    pub content: Option<field::Content>,
+    pub title: field::Title,
+    pub expires_at: field::ExpiresAt,
+    pub password: field::Password,
+    pub max_views: field::MaxViews,
+    pub burn_after_read: bool,
+    pub short_code: field::VanityCode,
+    pub visibility: field::Visibility,
+}
+
+impl Upload<'_> {
+    pub async fn into_ask(self) -> std::io::Result<ask::NewAttachment> {
+        Ok(ask::NewAttachment {
+            filename: filename(&self.file),
+            mime_type: self.file.content_type().map(ToString::to_string),
+            data: read_file(&self.file).await?,
+            content: self.content,
+            title: self.title,
+            expires_at: self.expires_at,
+            password: self.password,
+            max_views: self.max_views,
+            burn_after_read: self.burn_after_read,
+            short_code: self.short_code,
+            visibility: self.visibility,
+        })
+    }
+}
+
+/// the name as sent by the client, `field::Attachment` strips anything unsafe from it
+pub fn filename(file: &TempFile<'_>) -> String {
+    file.raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_owned())
+        .unwrap_or_default()
+}
+
+pub async fn read_file(file: &TempFile<'_>) -> std::io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut data = Vec::with_capacity(file.len() as usize);
+    file.open().await?.read_to_end(&mut data).await?;
+    Ok(data)
 }
 
 #[derive(Debug, Serialize, FromForm)]
 pub struct GetPasswordProtectedClip {
     pub password: field::Password,
 }
+
+#[derive(Debug, Serialize, FromForm)]
+pub struct DeleteClip {
+    pub token: field::ManagementToken,
+}
@@ -0,0 +1,89 @@
+use std::fmt::Write;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// larger clips are shown without colors, highlighting them would hold up the response
+const MAX_HIGHLIGHTED_SIZE: usize = 256 * 1024;
+const THEME: &str = "InspiredGitHub";
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME_: OnceLock<Theme> = OnceLock::new();
+    THEME_.get_or_init(|| {
+        ThemeSet::load_defaults()
+            .themes
+            .remove(THEME)
+            .expect("highlighting theme missing from the defaults")
+    })
+}
+
+/// renders content as a table with one row per line, rows have the ids `L1`, `L2`, ... so
+/// lines can be linked to
+///
+/// Unknown languages and oversized content are rendered as escaped plain text.
+pub fn highlight(content: &str, language: Option<&str>) -> String {
+    let syntaxes = syntaxes();
+    let syntax = language
+        .filter(|_| content.len() <= MAX_HIGHLIGHTED_SIZE)
+        .and_then(|language| syntaxes.find_syntax_by_token(language))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, theme());
+
+    let mut html = String::from(r#"<table class="code"><tbody>"#);
+    for (index, line) in LinesWithEndings::from(content).enumerate() {
+        let line_html = highlighter
+            .highlight_line(line, syntaxes)
+            .ok()
+            .and_then(|regions| {
+                styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()
+            })
+            .unwrap_or_else(|| handlebars::html_escape(line));
+        let number = index + 1;
+        let _ = write!(
+            html,
+            r##"<tr id="L{number}"><td class="line-number"><a href="#L{number}" data-line="{number}">{number}</a></td><td class="line">{}</td></tr>"##,
+            line_html.replace(['\r', '\n'], ""),
+            number = number
+        );
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::highlight;
+    use crate::domain::clip::field::LANGUAGES;
+
+    #[test]
+    fn knows_every_language() {
+        for (language, _) in LANGUAGES.iter().filter(|(language, _)| *language != "text") {
+            assert!(
+                super::syntaxes().find_syntax_by_token(language).is_some(),
+                "no syntax for {}",
+                language
+            );
+        }
+    }
+
+    #[test]
+    fn numbers_and_escapes_lines() {
+        let html = highlight("fn main() {}\n<script>alert(1)</script>\n", Some("rust"));
+        assert!(html.contains(r##"<tr id="L1"><td class="line-number"><a href="#L1""##));
+        assert!(html.contains(r#"<tr id="L2">"#));
+        assert!(!html.contains(r#"<tr id="L3">"#));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;"));
+
+        let html = highlight("<b>plain</b>", None);
+        assert!(html.contains("&lt;b&gt;plain&lt;/b&gt;"));
+    }
+}
@@ -1,54 +1,173 @@
-use crate::data::AppDatabase;
+use crate::data::{query::DeletionStatus, AppDatabase, BlobStore};
+use crate::domain::clip::field::ShortCodeGenerator;
+use crate::domain::retention::RetentionPolicy;
 use crate::service::action;
 use crate::service::{self, ask};
-use crate::web::{ctx, form, renderer::Renderer, PageError};
-use crate::{ServiceError, ShortCode};
+use crate::web::conditional::{self, Conditional, Conditions, Representation, Validators};
+use crate::web::{ctx, form, renderer::Renderer, BaseUrl, Download, PageError, RawClip};
+use crate::{ClipError, ServiceError, ShortCode};
+use rocket::data::Limits;
 use rocket::form::{Contextual, Form};
 use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::FlashMessage;
 use rocket::response::content::RawHtml;
-use rocket::response::{status, Redirect};
-use rocket::{uri, State};
+use rocket::response::{status, Flash, Redirect};
+use rocket::{uri, Either, State};
 
 use super::views::Views;
-use super::PASSWORD_COOKIE;
+use super::{password_from_cookie, PASSWORD_COOKIE};
+
+/// shows markdown clips rendered and every other clip as its source
+fn render_clip(
+    renderer: &Renderer<'_>,
+    clip: crate::Clip,
+    management_token: Option<String>,
+    clip_url: Option<String>,
+    errors: &[&str],
+) -> String {
+    use crate::domain::clip::field::Rendering;
+
+    if clip.rendering == Rendering::Markdown && clip.attachment.is_none() {
+        let mut context = ctx::RenderedClip::new(clip);
+        context.management_token = management_token;
+        context.clip_url = clip_url;
+        renderer.render(context, errors)
+    } else {
+        let mut context = ctx::ViewClip::new(clip);
+        context.management_token = management_token;
+        context.clip_url = clip_url;
+        renderer.render(context, errors)
+    }
+}
 
 #[rocket::get("/")]
-fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
-    let context = ctx::Home::default();
+fn home(flash: Option<FlashMessage<'_>>, renderer: &State<Renderer<'_>>) -> RawHtml<String> {
+    let context = ctx::Home {
+        notice: flash.map(|flash| flash.message().to_owned()),
+        ..Default::default()
+    };
 
     RawHtml(renderer.render(context, &[]))
 }
 
 #[rocket::post("/", data = "<form>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn new_clip(
-    form: Form<Contextual<'_, form::NewClip>>,
+    form: Form<Contextual<'_, form::NewClip<'_>>>,
     database: &State<AppDatabase>,
+    retention: &State<RetentionPolicy>,
+    short_codes: &State<ShortCodeGenerator>,
+    storage: &State<BlobStore>,
+    limits: &Limits,
+    base_url: BaseUrl,
     renderer: &State<Renderer<'_>>,
-) -> Result<Redirect, (Status, RawHtml<String>)> {
+) -> Result<status::Created<RawHtml<String>>, (Status, RawHtml<String>)> {
     let form = form.into_inner();
+    let too_large = format!(
+        "The file is larger than the upload limit of {}",
+        limits.get("file").unwrap_or(Limits::FILE)
+    );
+    let server_error = || {
+        (
+            Status::InternalServerError,
+            RawHtml(renderer.render(
+                ctx::Home::default(),
+                &["A server error occurred. Please try again"],
+            )),
+        )
+    };
 
     if let Some(value) = form.value {
-        let req = service::ask::NewClip {
-            content: value.content,
-            title: value.title,
-            exprires_at: value.expires_at,
-            password: value.password,
+        let file = match value.file {
+            Ok(file) if file.len() > 0 => Some(file),
+            Err(errors) if errors.status() == Status::PayloadTooLarge => {
+                let page = renderer.render_with_data(
+                    ctx::Home::default(),
+                    ("clip", &form.context),
+                    &[too_large.as_str()],
+                );
+                return Err((Status::PayloadTooLarge, RawHtml(page)));
+            }
+            _ => None,
         };
 
-        match action::new_clip(req, database.get_pool()).await {
-            Ok(clip) => Ok(Redirect::to(uri!(get_clip(short_code = clip.short_code)))),
+        let pool = database.get_pool();
+        let created = match file {
+            Some(file) => {
+                let data = match form::read_file(&file).await {
+                    Ok(data) => data,
+                    Err(e) => {
+                        eprintln!("failed to read upload: {}", e);
+                        return Err(server_error());
+                    }
+                };
+                let req = service::ask::NewAttachment {
+                    filename: form::filename(&file),
+                    mime_type: file.content_type().map(ToString::to_string),
+                    data,
+                    content: value.content,
+                    title: value.title,
+                    expires_at: value.expires_at,
+                    password: value.password,
+                    max_views: value.max_views,
+                    burn_after_read: value.burn_after_read,
+                    short_code: value.short_code,
+                    visibility: value.visibility,
+                };
+                action::upload_clip(req, None, retention, short_codes, storage, pool).await
+            }
+            None => match value.content {
+                Some(content) => {
+                    let req = service::ask::NewClip {
+                        content,
+                        title: value.title,
+                        exprires_at: value.expires_at,
+                        password: value.password,
+                        max_views: value.max_views,
+                        burn_after_read: value.burn_after_read,
+                        short_code: value.short_code,
+                        visibility: value.visibility,
+                        language: value.language,
+                        rendering: value.rendering,
+                    };
+                    action::new_clip(req, None, retention, short_codes, pool).await
+                }
+                None => Err(ClipError::EmptyContent.into()),
+            },
+        };
+        let form_error = |status, msg: &str| {
+            let page =
+                renderer.render_with_data(ctx::Home::default(), ("clip", &form.context), &[msg]);
+            (status, RawHtml(page))
+        };
+
+        match created {
+            Ok((clip, token)) => {
+                // the token is shown in this response only so it never ends up in a cookie, and
+                // following a redirect would burn a clip before it was ever shared
+                let clip_url = base_url.join(uri!(get_clip(short_code = clip.short_code.clone())));
+                let page = render_clip(
+                    renderer,
+                    clip,
+                    Some(token.into_inner()),
+                    Some(clip_url.clone()),
+                    &[],
+                );
+                Ok(status::Created::new(clip_url).body(RawHtml(page)))
+            }
+            Err(ServiceError::Conflict(msg)) => Err(form_error(Status::Conflict, &msg)),
+            Err(ServiceError::Retention(e)) => Err(form_error(Status::BadRequest, &e.to_string())),
+            Err(ServiceError::Clip(e)) => Err(form_error(Status::BadRequest, &e.to_string())),
             Err(e) => {
                 eprint!("internal error: {}", e);
-                Err((
-                    Status::InternalServerError,
-                    RawHtml(renderer.render(
-                        ctx::Home::default(),
-                        &["A server error occurred. Please try again"],
-                    )),
-                ))
+                Err(server_error())
             }
         }
     } else {
+        let status = match form.context.status() {
+            status if status == Status::PayloadTooLarge => status,
+            _ => Status::BadRequest,
+        };
         let error = form
             .context
             .errors()
@@ -57,6 +176,8 @@ pub async fn new_clip(
 
                 if let ErrorKind::Validation(msg) = &err.kind {
                     msg.as_ref()
+                } else if err.status() == Status::PayloadTooLarge {
+                    too_large.as_str()
                 } else {
                     eprintln!("unhandled error: {}", err);
                     "An error occurred, please try again"
@@ -65,7 +186,7 @@ pub async fn new_clip(
             .collect::<Vec<_>>();
 
         Err((
-            Status::BadRequest,
+            status,
             RawHtml(renderer.render_with_data(
                 ctx::Home::default(),
                 ("clip", &form.context),
@@ -78,32 +199,51 @@ pub async fn new_clip(
 #[rocket::get("/clip/<short_code>")]
 pub async fn get_clip(
     short_code: ShortCode,
+    cookies: &CookieJar<'_>,
+    flash: Option<FlashMessage<'_>>,
     database: &State<AppDatabase>,
     views: &State<Views>,
     renderer: &State<Renderer<'_>>,
-) -> Result<status::Custom<RawHtml<String>>, PageError> {
-    fn render_with_status<T: ctx::PageContext + serde::Serialize + std::fmt::Debug>(
-        status: Status,
-        context: T,
-        renderer: &Renderer,
-    ) -> Result<status::Custom<RawHtml<String>>, PageError> {
-        Ok(status::Custom(
-            status,
-            RawHtml(renderer.render(context, &[])),
-        ))
+    conditions: Conditions,
+) -> Result<Conditional<status::Custom<RawHtml<String>>>, PageError> {
+    let req = || ask::GetClip {
+        short_code: short_code.clone(),
+        password: password_from_cookie(cookies),
+        caller: None,
+    };
+
+    let pool = database.get_pool();
+    let html = Representation::Html;
+    if let Some(validators) = conditional::not_modified(&conditions, html, req(), pool).await {
+        return Ok(Conditional::NotModified(validators));
     }
 
-    match action::get_clip(short_code.clone().into(), database.get_pool()).await {
+    match action::get_clip(req(), pool).await {
         Ok(clip) => {
-            views.view(short_code.clone(), 1);
-            let context = ctx::ViewClip::new(clip);
-            render_with_status(Status::Ok, context, renderer)
+            views.view_clip(&clip);
+            let validators = Validators::new(&clip, html);
+            let mut errors = vec![];
+            if let Some(flash) = &flash {
+                if flash.kind() == "error" {
+                    errors.push(flash.message());
+                }
+            }
+            let page = render_clip(renderer, clip, None, None, &errors);
+            Ok(Conditional::Modified(
+                status::Custom(Status::Ok, RawHtml(page)),
+                validators,
+            ))
         }
         Err(e) => match e {
             ServiceError::PermissionError(_) => {
                 let context = ctx::PasswordRequired::new(short_code);
-                render_with_status(Status::Unauthorized, context, renderer)
+                let page = renderer.render(context, &[]);
+                Ok(Conditional::Uncached(status::Custom(
+                    Status::Unauthorized,
+                    RawHtml(page),
+                )))
             }
+            ServiceError::Forbidden(msg) => Err(PageError::Forbidden(msg)),
             ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
             _ => Err(PageError::Internal("server error".to_owned())),
         },
@@ -123,22 +263,23 @@ pub async fn submit_clip_password(
         let req = service::ask::GetClip {
             short_code: short_code.clone(),
             password: form.password.clone(),
+            caller: None,
         };
         match action::get_clip(req, database.get_pool()).await {
             Ok(clip) => {
-                views.view(short_code.clone(), 1);
-                let context = ctx::ViewClip::new(clip);
+                views.view_clip(&clip);
                 cookies.add(Cookie::new(
                     PASSWORD_COOKIE,
                     form.password.clone().into_inner().unwrap_or_default(),
                 ));
-                Ok(RawHtml(renderer.render(context, &[])))
+                Ok(RawHtml(render_clip(renderer, clip, None, None, &[])))
             }
             Err(e) => match e {
                 ServiceError::PermissionError(e) => {
                     let context = ctx::PasswordRequired::new(short_code);
                     Ok(RawHtml(renderer.render(context, &[e.as_str()])))
                 }
+                ServiceError::Forbidden(msg) => Err(PageError::Forbidden(msg)),
                 ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
                 _ => Err(PageError::Internal("server error".to_owned())),
             },
@@ -152,40 +293,203 @@ pub async fn submit_clip_password(
     }
 }
 
-#[rocket::get("/clip/raw/<short_code>")]
+#[derive(rocket::Responder)]
+pub enum RawResponse {
+    Clip(RawClip),
+    /// files are only ever served by `get_attachment`
+    Attachment(Redirect),
+    #[response(status = 401)]
+    PasswordRequired(String),
+}
+
+/// `?download=1` saves the clip as a file named after its title
+fn is_download(download: Option<&str>) -> bool {
+    download.is_some_and(|value| !matches!(value, "0" | "false" | "no" | "off"))
+}
+
+fn raw_response(
+    clip: Result<crate::Clip, ServiceError>,
+    download: Option<&str>,
+) -> Result<Conditional<RawResponse>, Status> {
+    match clip {
+        Ok(clip) if clip.attachment.is_some() => {
+            Ok(Conditional::Uncached(RawResponse::Attachment(
+                Redirect::to(uri!(get_attachment(short_code = clip.short_code))),
+            )))
+        }
+        Ok(clip) => {
+            let download = is_download(download);
+            let validators = Validators::new(&clip, Representation::raw(download));
+            let raw = RawClip::new(clip, download);
+            Ok(Conditional::Modified(RawResponse::Clip(raw), validators))
+        }
+        Err(e) => match e {
+            ServiceError::PermissionError(msg) => {
+                Ok(Conditional::Uncached(RawResponse::PasswordRequired(msg)))
+            }
+            ServiceError::Forbidden(_) => Err(Status::Forbidden),
+            ServiceError::NotFound => Err(Status::NotFound),
+            _ => Err(Status::InternalServerError),
+        },
+    }
+}
+
+#[rocket::get("/clip/raw/<short_code>?<download>")]
 pub async fn get_raw_clip(
     cookies: &CookieJar<'_>,
     short_code: ShortCode,
+    download: Option<&str>,
     views: &State<Views>,
     database: &State<AppDatabase>,
-) -> Result<status::Custom<String>, Status> {
-    use crate::domain::clip::field::Password;
+    conditions: Conditions,
+) -> Result<Conditional<RawResponse>, Status> {
+    let req = || ask::GetClip {
+        short_code: short_code.clone(),
+        password: password_from_cookie(cookies),
+        caller: None,
+    };
 
+    let pool = database.get_pool();
+    let raw = Representation::raw(is_download(download));
+    if let Some(validators) = conditional::not_modified(&conditions, raw, req(), pool).await {
+        return Ok(Conditional::NotModified(validators));
+    }
+    let clip = action::get_clip(req(), pool).await;
+    // the download of a file counts the view
+    match &clip {
+        Ok(clip) if clip.attachment.is_none() => views.view_clip(clip),
+        _ => (),
+    }
+    raw_response(clip, download)
+}
+
+/// answers with the headers of `get_raw_clip` without reading the clip
+#[rocket::head("/clip/raw/<short_code>?<download>")]
+pub async fn head_raw_clip(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    download: Option<&str>,
+    database: &State<AppDatabase>,
+    conditions: Conditions,
+) -> Result<Conditional<RawResponse>, Status> {
     let req = ask::GetClip {
-        short_code: short_code.clone(),
-        password: cookies
-            .get(PASSWORD_COOKIE)
-            .map(|cookie| cookie.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+        short_code,
+        password: password_from_cookie(cookies),
+        caller: None,
     };
 
-    match action::get_clip(req, database.get_pool()).await {
-        Ok(clip) => {
-            views.view(short_code.clone(), 1);
-            Ok(status::Custom(Status::Ok, clip.content.into_inner()))
+    let clip = action::peek_clip(req, database.get_pool()).await;
+    match &clip {
+        Ok(clip) if clip.attachment.is_none() => {
+            let validators = Validators::new(clip, Representation::raw(is_download(download)));
+            if conditions.is_fresh(&validators) {
+                return Ok(Conditional::NotModified(validators));
+            }
+        }
+        _ => (),
+    }
+    raw_response(clip, download)
+}
+
+#[rocket::get("/clip/download/<short_code>")]
+pub async fn get_attachment(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    views: &State<Views>,
+    database: &State<AppDatabase>,
+    storage: &State<BlobStore>,
+) -> Result<Download, Status> {
+    let req = ask::GetClip {
+        short_code,
+        password: password_from_cookie(cookies),
+        caller: None,
+    };
+
+    match action::get_attachment(req, storage, database.get_pool()).await {
+        Ok((clip, attachment, data)) => {
+            views.view_clip(&clip);
+            Ok(Download::new(&attachment, data))
         }
         Err(e) => match e {
-            ServiceError::PermissionError(msg) => Ok(status::Custom(Status::Unauthorized, msg)),
+            ServiceError::PermissionError(_) => Err(Status::Unauthorized),
+            ServiceError::Forbidden(_) => Err(Status::Forbidden),
             ServiceError::NotFound => Err(Status::NotFound),
             _ => Err(Status::InternalServerError),
         },
     }
 }
 
+/// ranked after `get_raw_clip`, which also matches `/clip/raw/history`
+#[rocket::get("/clip/<short_code>/history?<from>&<to>", rank = 2)]
+pub async fn get_history(
+    short_code: ShortCode,
+    from: Option<u64>,
+    to: Option<u64>,
+    cookies: &CookieJar<'_>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<Either<RawHtml<String>, Redirect>, PageError> {
+    let req = ask::GetClip {
+        short_code: short_code.clone(),
+        password: password_from_cookie(cookies),
+        caller: None,
+    };
+
+    match action::get_revisions(req, database.get_pool()).await {
+        Ok((clip, revisions)) => match ctx::History::new(clip, revisions, from, to) {
+            Some(context) => Ok(Either::Left(RawHtml(renderer.render(context, &[])))),
+            None => Err(PageError::NotFound("Revision not found".to_owned())),
+        },
+        // the clip page asks for the password and stores it for the history view
+        Err(ServiceError::PermissionError(_)) => {
+            Ok(Either::Right(Redirect::to(uri!(get_clip(short_code)))))
+        }
+        Err(ServiceError::Forbidden(msg)) => Err(PageError::Forbidden(msg)),
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("Clip not found".to_owned())),
+        Err(_) => Err(PageError::Internal("server error".to_owned())),
+    }
+}
+
+#[rocket::post("/clip/<short_code>/delete", data = "<form>")]
+pub async fn delete_clip(
+    short_code: ShortCode,
+    form: Form<Contextual<'_, form::DeleteClip>>,
+    database: &State<AppDatabase>,
+    storage: &State<BlobStore>,
+) -> Result<Flash<Redirect>, PageError> {
+    let back = |msg: &str| {
+        Flash::error(
+            Redirect::to(uri!(get_clip(short_code = short_code.clone()))),
+            msg,
+        )
+    };
+
+    let token = match &form.value {
+        Some(form) => form.token.clone(),
+        None => return Ok(back("A management token is required to delete this clip")),
+    };
+
+    let auth = ask::Authorization::Token(token);
+    match action::delete_clip(short_code.clone(), auth, storage, database.get_pool()).await {
+        Ok(DeletionStatus::Deleted) => Ok(Flash::success(Redirect::to(uri!(home)), "Clip deleted")),
+        Ok(DeletionStatus::NotFound) => Err(PageError::NotFound("Clip not found".to_owned())),
+        Err(ServiceError::PermissionError(msg)) => Ok(back(msg.as_str())),
+        Err(_) => Err(PageError::Internal("server error".to_owned())),
+    }
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![home, get_clip, new_clip, submit_clip_password, get_raw_clip]
+    rocket::routes![
+        home,
+        get_clip,
+        new_clip,
+        submit_clip_password,
+        get_raw_clip,
+        head_raw_clip,
+        get_attachment,
+        get_history,
+        delete_clip
+    ]
 }
 
 pub mod catcher {
@@ -194,13 +498,13 @@ pub mod catcher {
 
     #[catch(default)]
     fn default(req: &Request) -> &'static str {
-        eprintln!("general error: {:?}", req);
+        eprintln!("general error: {}", req);
         "something went wrong..."
     }
 
     #[catch(500)]
     fn internal_error(req: &Request) -> &'static str {
-        eprintln!("internal error: {:?}", req);
+        eprintln!("internal error: {}", req);
         "internal server error"
     }
 
@@ -237,7 +541,7 @@ pub mod test {
 
     #[test]
     fn requires_password_when_applicable() {
-        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
         use crate::service;
         use rocket::http::{ContentType, Cookie};
 
@@ -250,11 +554,26 @@ pub mod test {
             content: Content::new("content").unwrap(),
             exprires_at: ExpiresAt::default(),
             password: Password::new("123".to_owned()).unwrap(),
+            max_views: MaxViews::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
             title: Title::default(),
         };
 
-        let clip = rt
-            .block_on(async move { service::action::new_clip(req, db.get_pool()).await })
+        let (clip, _) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
             .unwrap();
 
         // Block clip when no password is provided
@@ -291,4 +610,558 @@ pub mod test {
             .dispatch();
         assert_eq!(response.status(), Status::Unauthorized);
     }
+
+    #[test]
+    fn shows_management_token_once() {
+        use rocket::http::ContentType;
+
+        let client = client();
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=content&title=&expires_at=&password=")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        assert!(response.cookies().iter().next().is_none());
+
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("will not be shown again"));
+
+        let response = client.get(location.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(!response
+            .into_string()
+            .unwrap()
+            .contains("will not be shown again"));
+    }
+
+    #[test]
+    fn deletes_clip_with_management_token() {
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = service::ask::NewClip {
+            content: Content::new("content").unwrap(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::default(),
+            max_views: MaxViews::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+            title: Title::default(),
+        };
+
+        let (clip, token) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        let short_code = clip.short_code.as_str();
+
+        // Send the user back to the clip when the token is incorrect
+        let response = client
+            .post(format!("/clip/{}/delete", short_code))
+            .header(ContentType::Form)
+            .body("token=incorrect")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let response = client.get(format!("/clip/{}", short_code)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("Invalid management token"));
+
+        let response = client
+            .post(format!("/clip/{}/delete", short_code))
+            .header(ContentType::Form)
+            .body(format!("token={}", token.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/"));
+
+        let response = client.get(format!("/clip/{}", short_code)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn burns_clip_after_first_read() {
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        // Creating the clip from the form must not consume it
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=secret&title=&expires_at=&password=&burn_after_read=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let req = service::ask::NewClip {
+            content: Content::new("secret").unwrap(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::default(),
+            max_views: MaxViews::default(),
+            burn_after_read: true,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+            title: Title::default(),
+        };
+        let (clip, _) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "secret");
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client
+            .get(format!("/clip/{}", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn expires_clip_after_max_views() {
+        use crate::domain::clip::field::{Content, ExpiresAt, MaxViews, Password, Title};
+        use crate::service;
+
+        let rt = async_runtime();
+
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = service::ask::NewClip {
+            content: Content::new("content").unwrap(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::default(),
+            max_views: MaxViews::new(2).unwrap(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+            title: Title::default(),
+        };
+        let (clip, _) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let response = client
+            .get(format!("/clip/{}", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn rejects_expiry_beyond_retention_policy() {
+        use crate::domain::retention::RetentionPolicy;
+        use crate::web::test::config;
+        use chrono::Duration;
+        use rocket::http::ContentType;
+        use rocket::local::blocking::Client;
+
+        let mut config = config();
+        config.retention = RetentionPolicy::new(None, Some(Duration::days(1))).unwrap();
+        let client = Client::tracked(crate::rocket(config)).unwrap();
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=content&title=&expires_at=2d&password=")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let body = response.into_string().unwrap();
+        assert!(body.contains("clips may not be kept longer than 1d"));
+        assert!(body.contains("content"));
+    }
+
+    #[test]
+    fn reports_taken_vanity_code() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let new_clip = || {
+            client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=content&title=&expires_at=&password=&short_code=deploy-notes")
+                .dispatch()
+        };
+
+        let response = new_clip();
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("/clip/deploy-notes")
+        );
+
+        let response = new_clip();
+        assert_eq!(response.status(), Status::Conflict);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("short code &#x27;deploy-notes&#x27; is already taken"));
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=content&title=&expires_at=&password=&short_code=raw")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response.into_string().unwrap().contains("is reserved"));
+    }
+
+    #[test]
+    fn shows_history_diff() {
+        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
+        use crate::service::{self, ask};
+
+        let rt = async_runtime();
+
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = ask::NewClip {
+            content: Content::new("kept line\nold line").unwrap(),
+            title: Title::default(),
+            exprires_at: ExpiresAt::default(),
+            password: Password::default(),
+            max_views: Default::default(),
+            burn_after_read: false,
+            short_code: Default::default(),
+            visibility: Default::default(),
+            language: Default::default(),
+            rendering: Default::default(),
+        };
+        let clip = rt
+            .block_on(async move {
+                let pool = db.get_pool();
+                let (clip, token) = service::action::new_clip(
+                    req,
+                    None,
+                    &Default::default(),
+                    &Default::default(),
+                    pool,
+                )
+                .await?;
+                let patch = ask::PatchClip {
+                    content: Some(Content::new("kept line\nnew line").unwrap()),
+                    ..Default::default()
+                };
+                let auth = ask::Authorization::Token(token);
+                service::action::patch_clip(clip.short_code, patch, auth, &Default::default(), pool)
+                    .await
+            })
+            .unwrap();
+
+        let response = client
+            .get(format!("/clip/{}/history", clip.short_code.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("- old line"));
+        assert!(body.contains("+ new line"));
+        assert!(body.contains("&nbsp; kept line"));
+
+        let response = client
+            .get(format!(
+                "/clip/{}/history?from=1&to=3",
+                clip.short_code.as_str()
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn rejects_private_clip_without_api_key() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=content&title=&expires_at=&password=&visibility=private")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("private clips have to be created with an API key"));
+    }
+
+    #[test]
+    fn uploads_file_from_home_form() {
+        use crate::web::test::multipart;
+
+        let client = client();
+        let fields = [
+            ("content", ""),
+            ("title", ""),
+            ("expires_at", ""),
+            ("password", ""),
+            ("visibility", "unlisted"),
+        ];
+        let (content_type, body) = multipart(&fields, ("shot.png", "image/png", b"\x89PNG"));
+        let response = client.post("/").header(content_type).body(body).dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        let short_code = location.trim_start_matches("/clip/");
+
+        let response = client.get(&location).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains(&format!("/clip/download/{}", short_code)));
+
+        let response = client.get(format!("/clip/raw/{}", short_code)).dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+
+        let response = client
+            .get(format!("/clip/download/{}", short_code))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("image/png")
+        );
+        assert_eq!(response.into_bytes().unwrap(), b"\x89PNG");
+
+        let fields = [("burn_after_read", "true")];
+        let (content_type, body) = multipart(&fields, ("shot.png", "image/png", b"\x89PNG"));
+        let response = client.post("/").header(content_type).body(body).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn highlights_code_and_keeps_raw_plain() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let content = "fn main() {\n    println!(\"<hi>\");\n}\n";
+        let body = format!(
+            "content={}&title=&expires_at=&password=&language=rs",
+            "fn%20main()%20%7B%0A%20%20%20%20println!(%22%3Chi%3E%22)%3B%0A%7D%0A"
+        );
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let page = client.get(&location).dispatch().into_string().unwrap();
+        assert!(page.contains(r#"<span class="tag is-info is-light ml-2">rust</span>"#));
+        assert!(page.contains(r#"<tr id="L3">"#));
+        assert!(page.contains("&lt;hi&gt;"));
+        assert!(!page.contains("<hi>"));
+
+        let short_code = location.trim_start_matches("/clip/");
+        let response = client.get(format!("/clip/raw/{}", short_code)).dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::Plain));
+        assert_eq!(response.into_string().unwrap(), content);
+    }
+
+    #[test]
+    fn renders_sanitized_markdown_with_source() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let body = format!(
+            "content={}&title=&expires_at=&password=&rendering=markdown",
+            "%23%20Hi%0A%0A%3Cscript%3Ealert(1)%3C%2Fscript%3E%0A%0A%5Bx%5D(javascript%3Aalert(1))"
+        );
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let page = client.get(&location).dispatch().into_string().unwrap();
+        assert!(page.contains("<h1>Hi</h1>"));
+        assert!(!page.contains("<script>alert"));
+        assert!(!page.contains(r#"href="javascript"#));
+        // the source stays available next to the rendered markdown
+        assert!(page.contains(r#"id="clip-source""#));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+
+    #[test]
+    fn raw_serves_language_type_and_downloads() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=%7B%22a%22%3A1%7D&title=My%20Notes!&expires_at=&password=&short_code=rawjson")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let response = client.get("/clip/raw/rawjson").dispatch();
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("application/json; charset=utf-8")
+        );
+        assert_eq!(response.headers().get_one("Content-Disposition"), None);
+        assert_eq!(response.into_string().unwrap(), r#"{"a":1}"#);
+
+        let response = client.get("/clip/raw/rawjson?download=1").dispatch();
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"My-Notes.json\"; filename*=UTF-8''My-Notes.json")
+        );
+    }
+
+    #[test]
+    fn burn_page_links_to_clip() {
+        use rocket::http::{uri::Host, ContentType};
+
+        let client = client();
+        let mut request = client.post("/").header(ContentType::Form).body(
+            "content=secret&title=&expires_at=&password=&short_code=burnlink&burn_after_read=true",
+        );
+        request
+            .inner_mut()
+            .set_host(Host::from(rocket::uri!("clips.example")));
+        let response = request.dispatch();
+        assert_eq!(response.status(), Status::Created);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains(r#"data-url="http://clips.example/clip/burnlink""#));
+    }
+
+    #[test]
+    fn head_on_raw_does_not_burn_clip() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=secret&title=&expires_at=&password=&short_code=rawburn&burn_after_read=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let response = client.head("/clip/raw/rawburn").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert!(response.into_bytes().unwrap_or_default().is_empty());
+
+        let response = client.get("/clip/raw/rawburn").dispatch();
+        assert_eq!(response.into_string().unwrap(), "secret");
+        let response = client.head("/clip/raw/rawburn").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn not_modified_does_not_count_views() {
+        use rocket::http::{ContentType, Header};
+
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=polled&title=&expires_at=&password=&short_code=polled&max_views=2")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let response = client.get("/clip/raw/polled").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let etag = response.headers().get_one("ETag").unwrap().to_owned();
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+        for _ in 0..3 {
+            let response = client
+                .get("/clip/raw/polled")
+                .header(Header::new("If-None-Match", etag.clone()))
+                .dispatch();
+            assert_eq!(response.status(), Status::NotModified);
+            assert!(response.into_bytes().unwrap_or_default().is_empty());
+        }
+
+        // the page is another representation, the raw entity tag does not match it
+        let response = client
+            .head("/clip/raw/polled?download=1")
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+
+        // the second and last view is still left
+        let response = client.get("/clip/polled").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+        let response = client.get("/clip/raw/polled").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
 }
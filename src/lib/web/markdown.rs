@@ -0,0 +1,87 @@
+use ammonia::{Builder, UrlRelative};
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::HashSet;
+
+/// renders markdown to HTML that is safe to embed in a page
+///
+/// The content is written by anyone, so everything the markdown produces, including raw HTML,
+/// goes through an allowlist: scripts, styles, forms, frames and event handlers are dropped and
+/// links only keep http, https and mailto URLs.
+pub fn render(content: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut unsafe_html = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, Parser::new_ext(content, options));
+
+    Builder::default()
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .url_relative(UrlRelative::PassThrough)
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::render;
+
+    #[test]
+    fn renders_markdown() {
+        let html = render("# Title\n\nSome **bold** text and a [link](https://example.com).");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+        assert!(html.contains(
+            r#"<a href="https://example.com" rel="noopener noreferrer nofollow">link</a>"#
+        ));
+    }
+
+    #[test]
+    fn strips_script_injection() {
+        let attacks = [
+            "<script>alert(1)</script>",
+            "<img src=x onerror=alert(1)>",
+            "<a href=\"javascript:alert(1)\">click</a>",
+            "[click](javascript:alert(1))",
+            "[click](JaVaScRiPt:alert(1))",
+            "![image](javascript:alert(1))",
+            "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">click</a>",
+            "<iframe src=\"https://example.com\"></iframe>",
+            "<svg onload=alert(1)></svg>",
+            "<div style=\"background:url(javascript:alert(1))\" onclick=\"alert(1)\">x</div>",
+            "<form action=\"https://example.com\"><input name=password></form>",
+            "<style>body { display: none }</style>",
+            "<<script>script>alert(1)<</script>/script>",
+        ];
+
+        for attack in attacks {
+            let html = render(attack).to_ascii_lowercase();
+            for forbidden in [
+                "<script",
+                "javascript:",
+                "data:",
+                "onerror",
+                "onload",
+                "onclick",
+                "<iframe",
+                "<svg",
+                "<form",
+                "<input",
+                "<style",
+                "style=",
+            ] {
+                assert!(
+                    !html.contains(forbidden),
+                    "{} survived in {:?} rendered as {:?}",
+                    forbidden,
+                    attack,
+                    html
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn escapes_code_blocks() {
+        let html = render("```\n<script>alert(1)</script>\n```");
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+}
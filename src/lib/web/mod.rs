@@ -1,14 +1,172 @@
 pub mod api;
+pub mod conditional;
 pub mod ctx;
+pub mod dto;
 pub mod form;
+pub mod highlight;
 pub mod http;
+pub mod markdown;
 pub mod renderer;
 pub mod views;
 
+pub use dto::{ClipPage, ClipSummary, ClipView, IssuedApiKey, RevisionView, SearchResult};
 pub use views::Views;
 
 pub const PASSWORD_COOKIE: &str = "password";
 
+pub(crate) fn password_from_cookie(
+    cookies: &rocket::http::CookieJar<'_>,
+) -> crate::domain::clip::field::Password {
+    use crate::domain::clip::field::Password;
+
+    cookies
+        .get(PASSWORD_COOKIE)
+        .map(|cookie| cookie.value())
+        .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+        .unwrap_or_default()
+}
+
+/// The scheme and host a request was sent to, for links that have to work outside the page.
+///
+/// `X-Forwarded-Proto` tells when a proxy terminated TLS, requests without a `Host` header get
+/// an empty base and with it relative links.
+pub struct BaseUrl(String);
+
+impl BaseUrl {
+    pub fn join(&self, path: impl std::fmt::Display) -> String {
+        format!("{}{}", self.0, path)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for BaseUrl {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(
+        req: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        let scheme = match req.headers().get_one("X-Forwarded-Proto") {
+            Some("https") => "https",
+            _ => "http",
+        };
+        let base = match req.host() {
+            Some(host) => format!("{}://{}", scheme, host),
+            None => String::new(),
+        };
+        rocket::request::Outcome::Success(BaseUrl(base))
+    }
+}
+
+/// The file of a clip, always sent as a download so it is never rendered on this origin.
+#[derive(rocket::Responder)]
+pub struct Download {
+    data: Vec<u8>,
+    content_type: rocket::http::ContentType,
+    disposition: rocket::http::Header<'static>,
+    nosniff: rocket::http::Header<'static>,
+}
+
+impl Download {
+    pub fn new(attachment: &crate::domain::clip::field::Attachment, data: Vec<u8>) -> Self {
+        use rocket::http::{ContentType, Header};
+
+        Self {
+            data,
+            content_type: ContentType::parse_flexible(attachment.mime_type())
+                .unwrap_or(ContentType::Binary),
+            disposition: Header::new(
+                "Content-Disposition",
+                content_disposition(attachment.filename()),
+            ),
+            nosniff: Header::new("X-Content-Type-Options", "nosniff"),
+        }
+    }
+}
+
+/// The text of a clip as served by the raw endpoint.
+pub struct RawClip {
+    content: String,
+    content_type: rocket::http::ContentType,
+    /// set when the clip is to be saved instead of shown
+    filename: Option<String>,
+}
+
+impl RawClip {
+    pub fn new(clip: crate::Clip, download: bool) -> Self {
+        use rocket::http::ContentType;
+
+        let filename = download.then(|| raw_filename(&clip));
+        let content_type = ContentType::parse_flexible(clip.language.mime_type())
+            .unwrap_or(ContentType::Plain)
+            .with_params(("charset", "utf-8"));
+        Self {
+            content: clip.content.into_inner(),
+            content_type,
+            filename,
+        }
+    }
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for RawClip {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let mut response = rocket::Response::build_from(rocket::response::Responder::respond_to(
+            self.content,
+            req,
+        )?);
+        response
+            .header(self.content_type)
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .raw_header("Content-Security-Policy", "sandbox");
+        if let Some(filename) = self.filename {
+            response.raw_header("Content-Disposition", content_disposition(&filename));
+        }
+        response.ok()
+    }
+}
+
+/// the title reduced to a safe file name with the extension of the clip's language, clips
+/// without a title are named after their short code
+fn raw_filename(clip: &crate::Clip) -> String {
+    let title = clip.title.clone().into_inner().unwrap_or_default();
+    let mut stem = String::with_capacity(title.len());
+    for c in title.trim().chars() {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            stem.push(c);
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    let stem = match stem.trim_end_matches('-') {
+        "" => clip.short_code.as_str(),
+        stem => stem,
+    };
+    format!("{}.{}", stem, clip.language.extension())
+}
+
+/// an ASCII `filename` for old clients and the exact name as RFC 5987 `filename*`
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
 #[derive(rocket::Responder)]
 pub enum PageError {
     #[response(status = 500)]
@@ -17,6 +175,9 @@ pub enum PageError {
     #[response(status = 500)]
     Render(String),
 
+    #[response(status = 403)]
+    Forbidden(String),
+
     #[response(status = 404)]
     NotFound(String),
 
@@ -42,25 +203,74 @@ pub mod test {
     use crate::RocketConfig;
     use rocket::local::blocking::Client;
 
+    /// the runtime the database of `config` lives on, views and maintenance keep running on it
+    pub fn runtime() -> &'static tokio::runtime::Runtime {
+        use std::sync::OnceLock;
+
+        static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(async_runtime)
+    }
+
     pub fn config() -> RocketConfig {
         use crate::web::{renderer::Renderer, views::Views};
-        let rt = async_runtime();
+        use rocket::data::ToByteUnit;
+
+        let rt = runtime();
         let renderer = Renderer::new("templates/".into());
         let database = crate::data::test::new_db(rt.handle());
         let maintenance = crate::domain::maintenance::Maintenance::spawn(
-            database.get_pool().clone(),
+            database.store(),
+            Default::default(),
             rt.handle().clone(),
         );
-        let views = Views::new(database.get_pool().clone(), rt.handle().clone());
+        let views = Views::new(database.store(), rt.handle().clone());
 
         RocketConfig {
             renderer,
             database,
             views,
             maintenance,
+            retention: Default::default(),
+            short_codes: Default::default(),
+            storage: Default::default(),
+            max_upload_size: 1.mebibytes(),
         }
     }
 
+    /// encodes text fields and a file as `multipart/form-data`
+    pub fn multipart(
+        fields: &[(&str, &str)],
+        file: (&str, &str, &[u8]),
+    ) -> (rocket::http::ContentType, Vec<u8>) {
+        const BOUNDARY: &str = "clipshare-test-boundary";
+
+        let mut body = vec![];
+        for (name, value) in fields {
+            body.extend(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                    BOUNDARY, name, value
+                )
+                .bytes(),
+            );
+        }
+        let (filename, content_type, data) = file;
+        body.extend(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+            Content-Type: {}\r\n\r\n",
+                BOUNDARY, filename, content_type
+            )
+            .bytes(),
+        );
+        body.extend(data);
+        body.extend(format!("\r\n--{}--\r\n", BOUNDARY).bytes());
+
+        let content_type = rocket::http::ContentType::new("multipart", "form-data")
+            .with_params(("boundary", BOUNDARY));
+        (content_type, body)
+    }
+
     pub fn client() -> Client {
         let config = config();
         Client::tracked(crate::rocket(config)).expect("failed to build rocket client")
@@ -1,6 +1,6 @@
 use crate::data::DatabasePool;
 use crate::service::{self, ServiceError};
-use crate::ShortCode;
+use crate::{Clip, ShortCode};
 use crossbeam_channel::TryRecvError;
 use crossbeam_channel::{unbounded, Sender};
 use parking_lot::Mutex;
@@ -33,7 +33,7 @@ impl Views {
     fn commit_views(
         store: ViewStore,
         handle: Handle,
-        pool: DatabasePool,
+        pool: Arc<DatabasePool>,
     ) -> Result<(), ViewsError> {
         let store = Arc::clone(&store);
 
@@ -45,9 +45,9 @@ impl Views {
         };
 
         handle.block_on(async move {
-            let transaction = service::action::begin_transaction(&pool).await?;
+            let transaction = service::action::begin_transaction(&*pool).await?;
             for (short_code, views) in store {
-                if let Err(e) = service::action::increase_views(&short_code, views, &pool).await {
+                if let Err(e) = service::action::increase_views(&short_code, views, &*pool).await {
                     eprintln!("error increasing views: {}", e);
                 }
             }
@@ -59,7 +59,7 @@ impl Views {
         msg: ViewMsg,
         store: ViewStore,
         handle: Handle,
-        pool: DatabasePool,
+        pool: Arc<DatabasePool>,
     ) -> Result<(), ViewsError> {
         match msg {
             ViewMsg::Commit => Self::commit_views(store.clone(), handle.clone(), pool.clone())?,
@@ -73,7 +73,7 @@ impl Views {
         Ok(())
     }
 
-    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
+    pub fn new(pool: Arc<DatabasePool>, handle: Handle) -> Self {
         let (tx, rx) = unbounded();
         let tx_clone = tx.clone();
         let rx_clone = rx.clone();
@@ -106,6 +106,13 @@ impl Views {
         Self { tx }
     }
 
+    /// counts a read of `clip`, unless `action::get_clip` already counted it against a view limit
+    pub fn view_clip(&self, clip: &Clip) {
+        if !clip.max_views.is_limited() {
+            self.view(clip.short_code.clone(), 1);
+        }
+    }
+
     pub fn view(&self, short_code: ShortCode, count: u32) {
         if let Err(e) = self.tx.send(ViewMsg::View(short_code, count)) {
             eprintln!("view count error: {}", e);
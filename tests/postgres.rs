@@ -0,0 +1,351 @@
+//! Runs the service against `PostgresStore`, whose queries are only checked here.
+//!
+//! The tests need a PostgreSQL database they may write to, `CLIPSHARE_TEST_POSTGRES` points to it
+//! and defaults to `postgres://postgres@localhost/clipshare_test`. They are ignored unless asked
+//! for with `cargo test -- --ignored`.
+
+use base64::{engine::general_purpose, Engine as _};
+use clipshare::data::query::{DeletionStatus, RevocationStatus};
+use clipshare::data::{AppDatabase, BlobStore};
+use clipshare::domain::clip::field::{
+    Content, ExpiresAt, MaxViews, Password, Title, VanityCode, Visibility,
+};
+use clipshare::domain::retention::RetentionPolicy;
+use clipshare::service::{action, ask};
+use clipshare::web::api::{ApiKey, RawApiKey, Scope};
+use clipshare::{ServiceError, ShortCode};
+
+fn database_url() -> String {
+    std::env::var("CLIPSHARE_TEST_POSTGRES")
+        .unwrap_or_else(|_| "postgres://postgres@localhost/clipshare_test".to_owned())
+}
+
+async fn database() -> AppDatabase {
+    let database = AppDatabase::new(&database_url()).await.unwrap();
+    database.migrate().await.unwrap();
+    database
+}
+
+/// a short code no other test run uses, the database is not emptied between runs
+fn unique_code() -> String {
+    format!("pg{}", &uuid::Uuid::new_v4().simple().to_string()[..16])
+}
+
+fn new_clip(content: &str, short_code: &str) -> ask::NewClip {
+    ask::NewClip {
+        content: Content::new(content).unwrap(),
+        title: Title::default(),
+        exprires_at: ExpiresAt::default(),
+        password: Password::default(),
+        max_views: MaxViews::default(),
+        burn_after_read: false,
+        short_code: VanityCode::new(short_code).unwrap(),
+        visibility: Visibility::default(),
+        language: Default::default(),
+        rendering: Default::default(),
+    }
+}
+
+async fn api_key(database: &AppDatabase) -> ApiKey {
+    let pool = database.get_pool();
+    let raw_key = action::generate_api_key(ask::NewApiKey::default(), pool)
+        .await
+        .unwrap();
+    action::authenticate_api_key(&raw_key, pool).await.unwrap()
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn migrations_apply_once() {
+    let database = database().await;
+
+    assert!(database.migrate().await.unwrap().is_empty());
+    assert!(database.schema_version().await.unwrap().is_some());
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn clip_new_update_and_history() {
+    let database = database().await;
+    let pool = database.get_pool();
+    let code = unique_code();
+    let retention = RetentionPolicy::default();
+
+    let (clip, token) = action::new_clip(
+        new_clip("first", &code),
+        None,
+        &retention,
+        &Default::default(),
+        pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(clip.short_code.as_str(), code);
+
+    let taken = action::new_clip(
+        new_clip("again", &code),
+        None,
+        &retention,
+        &Default::default(),
+        pool,
+    )
+    .await;
+    assert!(taken.is_err());
+
+    let update = ask::UpdateClip {
+        content: Content::new("second").unwrap(),
+        title: Title::new("titled".to_owned()),
+        exprires_at: ExpiresAt::default(),
+        password: Password::default(),
+        short_code: ShortCode::from(code.as_str()),
+        language: Default::default(),
+        rendering: Default::default(),
+    };
+    let auth = ask::Authorization::Token(token.clone());
+    let clip = action::update_clip(update, auth, &retention, pool)
+        .await
+        .unwrap();
+    assert_eq!(clip.content.as_str(), "second");
+    assert_eq!(clip.revision, 2);
+
+    let patch = ask::PatchClip {
+        title: ask::Patch::Clear,
+        ..Default::default()
+    };
+    let auth = ask::Authorization::Token(token);
+    let clip = action::patch_clip(clip.short_code, patch, auth, &retention, pool)
+        .await
+        .unwrap();
+    assert_eq!(clip.title.into_inner(), None);
+    assert_eq!(clip.content.as_str(), "second");
+    assert_eq!(clip.revision, 3);
+
+    let req = ask::GetClip::from_raw(&code);
+    let (_, revisions) = action::get_revisions(req, pool).await.unwrap();
+    let contents: Vec<_> = revisions
+        .iter()
+        .map(|revision| revision.content.as_str())
+        .collect();
+    assert_eq!(contents, ["first", "second"]);
+
+    let req = ask::GetClip::from_raw(&code);
+    let revision = action::get_revision(req, 2, pool).await.unwrap();
+    assert_eq!(revision.title.into_inner(), Some("titled".to_owned()));
+
+    let status = action::delete_clip(
+        ShortCode::from(code.as_str()),
+        ask::Authorization::Admin,
+        &BlobStore::Database,
+        pool,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(status, DeletionStatus::Deleted));
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn burned_clip_is_read_once() {
+    let database = database().await;
+    let pool = database.get_pool();
+    let code = unique_code();
+
+    let mut req = new_clip("secret", &code);
+    req.burn_after_read = true;
+    req.password = Password::new("hunter2".to_owned()).unwrap();
+    action::new_clip(req, None, &Default::default(), &Default::default(), pool)
+        .await
+        .unwrap();
+
+    let wrong = ask::GetClip::from_raw(&code);
+    assert!(matches!(
+        action::get_clip(wrong, pool).await,
+        Err(ServiceError::PermissionError(_))
+    ));
+
+    let mut req = ask::GetClip::from_raw(&code);
+    req.password = Password::new("hunter2".to_owned()).unwrap();
+    let clip = action::get_clip(req, pool).await.unwrap();
+    assert_eq!(clip.content.as_str(), "secret");
+
+    let again = ask::GetClip::from_raw(&code);
+    assert!(matches!(
+        action::get_clip(again, pool).await,
+        Err(ServiceError::NotFound)
+    ));
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn list_and_search_own_clips() {
+    let database = database().await;
+    let pool = database.get_pool();
+    let owner = api_key(&database).await;
+    let word = unique_code();
+
+    for n in 0..3 {
+        let content = format!("clip number {} mentions {}", n, word);
+        action::new_clip(
+            new_clip(&content, &unique_code()),
+            Some(&owner),
+            &Default::default(),
+            &Default::default(),
+            pool,
+        )
+        .await
+        .unwrap();
+    }
+
+    let req = ask::ListClips {
+        limit: Some(2),
+        ..Default::default()
+    };
+    let first = action::list_clips(&owner, req, pool).await.unwrap();
+    assert_eq!(first.items.len(), 2);
+    let req = ask::ListClips {
+        cursor: first.next_cursor,
+        limit: Some(2),
+        ..Default::default()
+    };
+    let second = action::list_clips(&owner, req, pool).await.unwrap();
+    assert_eq!(second.items.len(), 1);
+    assert!(second.next_cursor.is_none());
+
+    let req = ask::SearchClips {
+        query: format!("{} number", word),
+        limit: None,
+    };
+    let hits = action::search_clips(&owner, req, pool).await.unwrap();
+    assert_eq!(hits.len(), 3);
+    assert!(hits.iter().all(|hit| hit.snippet.contains(&word)));
+    assert!(hits.windows(2).all(|hits| hits[0].rank <= hits[1].rank));
+
+    let stranger = api_key(&database).await;
+    let req = ask::SearchClips {
+        query: word,
+        limit: None,
+    };
+    assert!(action::search_clips(&stranger, req, pool)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn api_keys_authenticate_until_revoked() {
+    let database = database().await;
+    let pool = database.get_pool();
+
+    let req = ask::NewApiKey {
+        name: "integration".to_owned(),
+        scopes: vec![Scope::ClipRead],
+        expires_at: ExpiresAt::default(),
+    };
+    let raw_key = action::generate_api_key(req, pool).await.unwrap();
+    let api_key = action::authenticate_api_key(&raw_key, pool).await.unwrap();
+    assert_eq!(api_key.scopes(), [Scope::ClipRead]);
+
+    let keys = action::list_api_keys(pool).await.unwrap();
+    let details = keys
+        .iter()
+        .find(|key| key.prefix == raw_key.prefix())
+        .unwrap();
+    assert_eq!(details.name, "integration");
+    assert!(details.last_used_at.is_some());
+
+    let status = action::revoke_api_key(&raw_key.prefix(), pool)
+        .await
+        .unwrap();
+    assert!(matches!(status, RevocationStatus::Revoked));
+    assert!(matches!(
+        action::authenticate_api_key(&raw_key, pool).await,
+        Err(ServiceError::NotFound)
+    ));
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn legacy_api_key_upgrades_once() {
+    let database = database().await;
+    let pool = database.get_pool();
+
+    // keys issued before hashing were stored as they were handed out, under a placeholder prefix
+    let legacy = uuid::Uuid::new_v4().as_bytes().to_vec();
+    let prefix = unique_code();
+    let raw = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    sqlx::query(
+        r#"INSERT INTO api_keys (api_key, prefix, scopes, created_at)
+        VALUES ($1, $2, 'clip:read', now() AT TIME ZONE 'utc')"#,
+    )
+    .bind(&legacy)
+    .bind(&prefix)
+    .execute(&raw)
+    .await
+    .unwrap();
+
+    let raw_key: RawApiKey = general_purpose::STANDARD.encode(&legacy).parse().unwrap();
+    assert!(pool.get_legacy_api_key(&legacy).await.unwrap().is_some());
+    let api_key = action::authenticate_api_key(&raw_key, pool).await.unwrap();
+    assert_eq!(api_key.prefix(), raw_key.prefix());
+    assert!(pool.get_legacy_api_key(&legacy).await.unwrap().is_none());
+
+    // a concurrent request that read the key before it was upgraded
+    let again = pool
+        .upgrade_api_key(&legacy, &raw_key.prefix(), &raw_key.hash())
+        .await
+        .unwrap();
+    assert_eq!(again, api_key.into_inner());
+
+    action::revoke_api_key(&raw_key.prefix(), pool)
+        .await
+        .unwrap();
+}
+
+#[rocket::async_test]
+#[ignore = "needs a local PostgreSQL, see the module documentation"]
+async fn attachments_are_stored_and_counted() {
+    let database = database().await;
+    let pool = database.get_pool();
+    let storage = BlobStore::Database;
+    let code = unique_code();
+
+    let req = ask::NewAttachment {
+        content: None,
+        title: Title::default(),
+        expires_at: ExpiresAt::default(),
+        password: Password::default(),
+        max_views: MaxViews::default(),
+        burn_after_read: false,
+        short_code: VanityCode::new(&code).unwrap(),
+        visibility: Visibility::default(),
+        filename: "notes.txt".to_owned(),
+        mime_type: None,
+        data: b"attached".to_vec(),
+    };
+    let (clip, _) = action::upload_clip(
+        req,
+        None,
+        &Default::default(),
+        &Default::default(),
+        &storage,
+        pool,
+    )
+    .await
+    .unwrap();
+
+    let req = ask::GetClip::from_raw(&code);
+    let (_, _, data) = action::get_attachment(req, &storage, pool).await.unwrap();
+    assert_eq!(data, b"attached");
+
+    let stats = action::stats(pool).await.unwrap();
+    assert!(stats.attachments >= 1);
+    assert!(stats.attachment_bytes >= 8);
+    assert!(stats.clips >= stats.attachments);
+
+    action::delete_clip(clip.short_code, ask::Authorization::Admin, &storage, pool)
+        .await
+        .unwrap();
+    let req = ask::GetClip::from_raw(&code);
+    assert!(action::get_attachment(req, &storage, pool).await.is_err());
+}